@@ -14,6 +14,42 @@ use rusqlite;
 
 pub type Result<T> = ::std::result::Result<T, QuizError>;
 
+/// A source of the current time. Everything that schedules questions or records results
+/// asks a `Clock` for the time instead of calling `Utc::now` directly, so that the time
+/// can be changed with the `--now` option.
+pub trait Clock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+/// A clock that tells the real time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+}
+
+/// A clock that was set to a different time when it was created, and has run normally
+/// since then.
+pub struct OffsetClock {
+    offset: chrono::Duration,
+}
+
+impl OffsetClock {
+    pub fn new(start: chrono::DateTime<chrono::Utc>) -> Self {
+        OffsetClock {
+            offset: start - chrono::Utc::now(),
+        }
+    }
+}
+
+impl Clock for OffsetClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now() + self.offset
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Location {
     pub line: usize,
@@ -27,6 +63,8 @@ pub enum QuizError {
     /// For JSON errors.
     Json(serde_json::Error),
     CannotWriteToFile(PathBuf),
+    /// For when a command would overwrite an existing file.
+    FileAlreadyExists(PathBuf),
     Io(io::Error),
     Sql(rusqlite::Error),
     ReadlineInterrupted,
@@ -37,11 +75,50 @@ pub enum QuizError {
         message: String,
     },
     CannotOpenEditor,
+    /// For when there are too few results to fit a memory model.
+    NotEnoughHistory {
+        needed: usize,
+        found: usize,
+    },
+    /// For when a migrated database does not match the quiz it was migrated from.
+    MigrationMismatch(String),
+    /// For when a quiz uses a feature that cannot be stored in a database.
+    CannotMigrate(String),
+    /// For when a question loaded from a database lacks the answers it needs to be asked.
+    MalformedQuestion {
+        id: i64,
+        message: String,
+    },
+    /// For when `--resume` is given but the last session was not interrupted.
+    NothingToResume,
+    /// For when a results file cannot be parsed, e.g. because it was cut short. `backup`
+    /// is the previous version of the file, if there is one.
+    CorruptedResults {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+        message: String,
+    },
+    /// For when the fitted FSRS parameters of a quiz cannot be parsed.
+    CorruptedParameters {
+        path: PathBuf,
+        message: String,
+    },
+    /// For when a results file given on the command line cannot be read or parsed.
+    BadResultsFile {
+        path: PathBuf,
+        message: String,
+    },
     /// Not really an error, but a signal sent when the user wants to mark their
     /// previous answer as correct.
     SignalMarkCorrect,
     /// A signal sent when the user wants to edit the previous question.
     SignalEdit,
+    /// A signal sent when the user wants to stop being asked the previous question.
+    SignalSuspend,
+    /// A signal sent when the user wants to skip the previous question until tomorrow.
+    SignalBury,
+    /// A signal sent when the user wants to flag the previous question with a note.
+    SignalFlag(String),
 }
 
 impl fmt::Display for QuizError {
@@ -54,6 +131,9 @@ impl fmt::Display for QuizError {
             QuizError::CannotWriteToFile(ref path) => {
                 write!(f, "could not write to file '{}'", path.to_string_lossy())
             }
+            QuizError::FileAlreadyExists(ref path) => {
+                write!(f, "file '{}' already exists", path.to_string_lossy())
+            }
             QuizError::Io(ref err) => write!(f, "IO error ({})", err),
             QuizError::Sql(ref err) => write!(f, "SQL error ({})", err),
             QuizError::EmptyQuiz => write!(f, "no questions found"),
@@ -72,8 +152,65 @@ impl fmt::Display for QuizError {
                 write!(f, "{} {}", message, location)
             }
             QuizError::CannotOpenEditor => write!(f, "unable to open text editor"),
+            QuizError::NotEnoughHistory { needed, found } => write!(
+                f,
+                "not enough review history to fit parameters (need {} reviews, found {})",
+                needed, found
+            ),
+            QuizError::MigrationMismatch(ref message) => {
+                write!(f, "migrated database does not match quiz ({})", message)
+            }
+            QuizError::CannotMigrate(ref message) => write!(f, "cannot migrate quiz ({})", message),
+            QuizError::MalformedQuestion { id, ref message } => {
+                write!(f, "question {} is malformed ({})", id, message)
+            }
+            QuizError::NothingToResume => write!(f, "no interrupted session to resume"),
+            QuizError::CorruptedResults {
+                ref path,
+                ref backup,
+                ref message,
+            } => {
+                write!(
+                    f,
+                    "results file '{}' is corrupted or truncated ({}); ",
+                    path.to_string_lossy(),
+                    message
+                )?;
+                if let Some(backup) = backup {
+                    write!(
+                        f,
+                        "restore the previous version from '{}' or move the file aside \
+                         to start over",
+                        backup.to_string_lossy()
+                    )
+                } else {
+                    write!(f, "move the file aside to start over")
+                }
+            }
+            QuizError::CorruptedParameters {
+                ref path,
+                ref message,
+            } => write!(
+                f,
+                "FSRS parameters file '{}' is corrupted or truncated ({}); run --fit again \
+                 or delete the file to use the default parameters",
+                path.to_string_lossy(),
+                message
+            ),
+            QuizError::BadResultsFile {
+                ref path,
+                ref message,
+            } => write!(
+                f,
+                "could not read results from '{}' ({})",
+                path.to_string_lossy(),
+                message
+            ),
             QuizError::SignalMarkCorrect => write!(f, "internal error ('SignalMarkCorrect')"),
             QuizError::SignalEdit => write!(f, "internal error ('SignalEdit')"),
+            QuizError::SignalSuspend => write!(f, "internal error ('SignalSuspend')"),
+            QuizError::SignalBury => write!(f, "internal error ('SignalBury')"),
+            QuizError::SignalFlag(_) => write!(f, "internal error ('SignalFlag')"),
         }
     }
 }
@@ -91,11 +228,19 @@ impl error::Error for QuizError {
 pub struct Options {
     /// Do not emit colorized output.
     pub no_color: bool,
+    /// Pretend that the program was started at this time instead of the real time.
+    pub now: Option<chrono::DateTime<chrono::Utc>>,
     pub cmd: Command,
 }
 
 pub enum Command {
+    ConvertResults(ConvertOptions),
+    Fit(FitOptions),
+    Forecast(ForecastOptions),
+    MergeResults(MergeOptions),
+    Migrate(MigrateOptions),
     Results(ResultsOptions),
+    Simulate(SimulateOptions),
     Take(TakeOptions),
 }
 
@@ -105,10 +250,58 @@ pub struct FilterOptions {
     pub tags: Vec<String>,
 }
 
+pub struct ConvertOptions {
+    /// Name of the quiz whose results should be converted.
+    pub name: PathBuf,
+    pub to: ResultsFormat,
+}
+
+pub struct FitOptions {
+    /// Name of the quiz whose memory model should be fitted.
+    pub name: PathBuf,
+}
+
+pub struct MergeOptions {
+    /// Name of the quiz whose results should be merged into.
+    pub name: PathBuf,
+    /// Paths of the results files to merge, in either format.
+    pub paths: Vec<PathBuf>,
+}
+
+pub struct MigrateOptions {
+    /// Name of the quiz to migrate.
+    pub name: PathBuf,
+    /// Path of the SQLite database to create.
+    pub output: PathBuf,
+}
+
+pub struct ForecastOptions {
+    pub name: PathBuf,
+    /// Forecast every quiz in the current directory instead of just `name`.
+    pub all: bool,
+    pub days: usize,
+    pub json: bool,
+    pub scheduler: Option<SchedulerKind>,
+}
+
 pub struct ResultsOptions {
     pub name: PathBuf,
 }
 
+pub struct SimulateOptions {
+    /// The options for each simulated session. `take.name` is the quiz to simulate.
+    pub take: TakeOptions,
+    pub days: usize,
+    pub seed: u64,
+    /// Days until the simulated learner's probability of recalling a new question
+    /// falls to 1/e.
+    pub initial_strength: f64,
+    /// Factor by which the learner's memory strength grows after a correct answer.
+    pub growth: f64,
+    /// Start from the quiz's real results instead of a blank slate.
+    pub replay: bool,
+}
+
 pub struct TakeOptions {
     /// Name of the quiz to take.
     pub name: PathBuf,
@@ -117,6 +310,77 @@ pub struct TakeOptions {
     pub no_save: bool,
     pub num_to_ask: usize,
     pub filter_opts: FilterOptions,
+    /// Overrides the scheduler chosen in the quiz file, if any.
+    pub scheduler: Option<SchedulerKind>,
+    /// Only ask questions whose due date has passed.
+    pub due: bool,
+    /// Re-ask missed questions later in the session until they are answered correctly.
+    pub relearn: bool,
+    /// If set, ask every question that matches the filters, regardless of when it is
+    /// due, until it has been answered correctly this many times in a row.
+    pub cram: Option<usize>,
+    /// The seed for the session's random number generator. A random seed is used if
+    /// not given.
+    pub seed: Option<u64>,
+    /// Resume the interrupted session, if there is one, without asking the user.
+    pub resume: bool,
+}
+
+/// The available algorithms for choosing which questions to ask.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerKind {
+    Buckets,
+    Fsrs,
+    Sm2,
+}
+
+impl SchedulerKind {
+    /// Return the name used for the scheduler in quiz files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SchedulerKind::Buckets => "buckets",
+            SchedulerKind::Fsrs => "fsrs",
+            SchedulerKind::Sm2 => "sm2",
+        }
+    }
+
+    /// Look up a scheduler by the name used in quiz files and on the command line.
+    pub fn from_name(name: &str) -> Option<SchedulerKind> {
+        match name {
+            "buckets" => Some(SchedulerKind::Buckets),
+            "fsrs" => Some(SchedulerKind::Fsrs),
+            "sm2" => Some(SchedulerKind::Sm2),
+            _ => None,
+        }
+    }
+}
+
+/// The formats in which results can be stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultsFormat {
+    /// A single JSON object mapping question IDs to lists of results.
+    Json,
+    /// A log with one JSON line per result, which is only ever appended to.
+    Jsonl,
+}
+
+impl ResultsFormat {
+    /// Return the name used for the format on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResultsFormat::Json => "json",
+            ResultsFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Look up a format by the name used on the command line.
+    pub fn from_name(name: &str) -> Option<ResultsFormat> {
+        match name {
+            "json" => Some(ResultsFormat::Json),
+            "jsonl" => Some(ResultsFormat::Jsonl),
+            _ => None,
+        }
+    }
 }
 
 /// Return `true` if `tags` satisfies the constraints in `options`.
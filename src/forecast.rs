@@ -0,0 +1,129 @@
+/**
+ * Forecast how many questions will fall due for review on each of the coming days.
+ *
+ * The forecast only looks at the results recorded so far: each question is counted
+ * once, on the day that the active scheduler says it is next due. Questions that are
+ * overdue or have never been asked are counted as due today, buried questions are
+ * counted on the day they are unburied, and suspended questions are not counted at
+ * all. Reviewing a question pushes its next due date back, so later days in the
+ * forecast are a lower bound.
+ *
+ * Author:  Ian Fisher (iafisher@fastmail.com)
+ * Version: October 2019
+ */
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use colored::*;
+use serde::Serialize;
+
+use super::common::{QuizError, Result, SchedulerKind};
+use super::quiz::Quiz;
+use super::repetition;
+
+// The length of the longest bar in the chart.
+const MAX_BAR_WIDTH: usize = 50;
+
+/// The number of questions due on each day, for one or more quizzes.
+#[derive(Serialize, Debug)]
+pub struct Forecast {
+    pub start: chrono::DateTime<chrono::Utc>,
+    /// The number of questions due on each day across all the quizzes. The first day
+    /// is the day that `start` falls on, according to each quiz's calendar.
+    pub total: Vec<usize>,
+    /// The number of questions due on each day in each quiz, keyed by the quiz's path.
+    pub quizzes: BTreeMap<String, Vec<usize>>,
+    /// The calendar of the first quiz added, used to label the days of the chart.
+    #[serde(skip)]
+    pub calendar: repetition::Calendar,
+}
+
+impl Forecast {
+    pub fn new(start: chrono::DateTime<chrono::Utc>, days: usize) -> Self {
+        Forecast {
+            start,
+            total: vec![0; days],
+            quizzes: BTreeMap::new(),
+            calendar: repetition::Calendar::default(),
+        }
+    }
+
+    /// Add the questions of `quiz` to the forecast. `requested` is the scheduler
+    /// chosen on the command line, if any.
+    pub fn add(&mut self, name: &str, quiz: &Quiz, requested: Option<SchedulerKind>) {
+        let scheduler = repetition::get_scheduler(
+            quiz.scheduler_kind(requested),
+            &quiz.fsrs_parameters,
+            quiz.calendar,
+        );
+        let today = quiz.calendar.day_of(self.start);
+        if self.quizzes.is_empty() {
+            self.calendar = quiz.calendar;
+        }
+
+        let mut counts = vec![0; self.total.len()];
+        for q in quiz.questions.iter() {
+            if let Some(threshold) = quiz.suspend_after() {
+                if repetition::count_lapses(q.as_ref()) >= threshold {
+                    continue;
+                }
+            }
+
+            if repetition::is_suspended(q.as_ref()) {
+                continue;
+            }
+
+            let due = scheduler.due(&q.get_common().prior_results);
+            let due = match (due, repetition::buried_until(q.as_ref())) {
+                (Some(due), Some(buried_until)) => Some(due.max(buried_until)),
+                (due, buried_until) => due.or(buried_until),
+            };
+            let day = if let Some(due) = due {
+                let days = (quiz.calendar.day_of(due) - today).num_days().max(0);
+                days as usize
+            } else {
+                0
+            };
+
+            if day < counts.len() {
+                counts[day] += 1;
+                self.total[day] += 1;
+            }
+        }
+        self.quizzes.insert(String::from(name), counts);
+    }
+}
+
+/// Print the forecast as a bar chart, one row per day.
+pub fn print_chart(forecast: &Forecast) -> Result<()> {
+    let max = *forecast.total.iter().max().unwrap_or(&0);
+    let today = forecast.calendar.day_of(forecast.start);
+    for (i, count) in forecast.total.iter().enumerate() {
+        let label = if i == 0 {
+            String::from("Today")
+        } else {
+            let date = today + chrono::Duration::days(i as i64);
+            date.format("%a %b %e").to_string()
+        };
+
+        // Scale the bars only if the longest one would not fit.
+        let width = if max > MAX_BAR_WIDTH {
+            (count * MAX_BAR_WIDTH + max - 1) / max
+        } else {
+            *count
+        };
+        if width > 0 {
+            my_println!("{:<10}  {:>4}  {}", label, count, "#".repeat(width).cyan())?;
+        } else {
+            my_println!("{:<10}  {:>4}", label, count)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the forecast as JSON.
+pub fn print_json(forecast: &Forecast) -> Result<()> {
+    let serialized = serde_json::to_string_pretty(forecast).map_err(QuizError::Json)?;
+    my_println!("{}", serialized)?;
+    Ok(())
+}
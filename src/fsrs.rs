@@ -0,0 +1,315 @@
+/**
+ * A memory model in the style of FSRS (Free Spaced Repetition Scheduler), used to
+ * estimate how likely the user is to remember the answer to a question.
+ *
+ * Each question has a stability, the number of days after which the probability of
+ * recall falls to 90%, and a difficulty between 1 and 10. Both are computed by
+ * replaying the question's results in order, and the probability of recall after `t`
+ * days is `(1 + t / (9 * stability)) ^ -1`.
+ *
+ * The model has 17 parameters, which start at the published FSRS defaults and can be
+ * re-fitted to the user's own review history with `fit`.
+ *
+ * Author:  Ian Fisher (iafisher@fastmail.com)
+ * Version: October 2019
+ */
+use serde::{Deserialize, Serialize};
+
+use super::quiz::QuestionResult;
+
+/// The default weights from version 4 of FSRS.
+const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26, 0.29,
+    2.61,
+];
+// The lower and upper bound of each weight while fitting.
+const WEIGHT_BOUNDS: [(f64, f64); 17] = [
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (1.0, 10.0),
+    (0.01, 5.0),
+    (0.01, 5.0),
+    (0.0, 1.0),
+    (0.0, 5.0),
+    (0.0, 1.0),
+    (0.01, 5.0),
+    (0.01, 5.0),
+    (0.0, 1.0),
+    (0.01, 1.0),
+    (0.01, 5.0),
+    (0.0, 1.0),
+    (1.0, 10.0),
+];
+/// The fewest reviews that `fit` will accept. A review is a result that follows an
+/// earlier result for the same question, since the first result has nothing to predict.
+pub const MINIMUM_REVIEWS_TO_FIT: usize = 10;
+// Scores at or below this count as forgetting the answer.
+const AGAIN_THRESHOLD: u64 = 400;
+// Scores below this (but above `AGAIN_THRESHOLD`) count as remembering with difficulty.
+const HARD_THRESHOLD: u64 = 900;
+// The score that a slow but correct answer is given, which counts as remembering with
+// difficulty.
+const SLOW_ANSWER_SCORE: u64 = 800;
+
+/// The parameters of the memory model for a particular quiz.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Parameters {
+    pub weights: [f64; 17],
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            weights: DEFAULT_WEIGHTS,
+        }
+    }
+}
+
+/// The memory state of a single question.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryState {
+    /// Days until the probability of recall falls to 90%.
+    pub stability: f64,
+    /// Between 1 (easiest) and 10 (hardest).
+    pub difficulty: f64,
+    pub last_review: chrono::DateTime<chrono::Utc>,
+}
+
+/// Compute the memory state of a question from its results, or return `None` if the
+/// question has never been asked. `results` must be in chronological order.
+pub fn memory_state(parameters: &Parameters, results: &Vec<QuestionResult>) -> Option<MemoryState> {
+    let w = &parameters.weights;
+    let mut state = initial_state(w, results.first()?);
+
+    for result in results.iter().skip(1) {
+        let elapsed = days_between(state.last_review, result.time_asked);
+        update(
+            w,
+            &mut state,
+            elapsed,
+            score_to_grade(result.recall_score(SLOW_ANSWER_SCORE)),
+        );
+        state.last_review = result.time_asked;
+    }
+    Some(state)
+}
+
+/// Return the probability that the user remembers the answer to a question at `now`.
+pub fn retrievability(state: &MemoryState, now: chrono::DateTime<chrono::Utc>) -> f64 {
+    forgetting_curve(days_between(state.last_review, now), state.stability)
+}
+
+/// Fit the parameters to the history in `results`, where each element of `results`
+/// is the chronologically-ordered list of results for one question. Return the fitted
+/// parameters along with the log loss before and after fitting, or `None` if there
+/// are fewer than `MINIMUM_REVIEWS_TO_FIT` reviews.
+///
+/// The fit is a simple coordinate search: each weight in turn is nudged up and down,
+/// and the change is kept if it lowers the loss. The step size is halved whenever a
+/// full pass makes no progress.
+pub fn fit(
+    start: &Parameters,
+    results: &Vec<&Vec<QuestionResult>>,
+) -> Option<(Parameters, f64, f64)> {
+    if count_reviews(results) < MINIMUM_REVIEWS_TO_FIT {
+        return None;
+    }
+
+    let mut weights = start.weights;
+    let initial_loss = log_loss(&weights, results);
+    let mut loss = initial_loss;
+    let mut step = 0.5;
+    while step > 0.001 {
+        let mut improved = false;
+        for i in 0..weights.len() {
+            for direction in [1.0, -1.0].iter() {
+                let (lower, upper) = WEIGHT_BOUNDS[i];
+                let old = weights[i];
+                let new = (old + direction * step * old.abs().max(0.1))
+                    .max(lower)
+                    .min(upper);
+                weights[i] = new;
+                let new_loss = log_loss(&weights, results);
+                if new_loss < loss {
+                    loss = new_loss;
+                    improved = true;
+                    break;
+                } else {
+                    weights[i] = old;
+                }
+            }
+        }
+
+        if !improved {
+            step /= 2.0;
+        }
+    }
+
+    Some((Parameters { weights }, initial_loss, loss))
+}
+
+/// Return the number of results in `results` that follow an earlier result for the
+/// same question.
+pub fn count_reviews(results: &Vec<&Vec<QuestionResult>>) -> usize {
+    results.iter().map(|r| r.len().saturating_sub(1)).sum()
+}
+
+/// The mean binary cross-entropy between the predicted probability of recall and
+/// whether the user actually recalled the answer, over every review.
+fn log_loss(weights: &[f64], results: &Vec<&Vec<QuestionResult>>) -> f64 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for history in results.iter() {
+        if history.len() < 2 {
+            continue;
+        }
+
+        let mut state = initial_state(weights, &history[0]);
+        for result in history.iter().skip(1) {
+            let elapsed = days_between(state.last_review, result.time_asked);
+            let predicted = forgetting_curve(elapsed, state.stability)
+                .max(0.0001)
+                .min(0.9999);
+            let grade = score_to_grade(result.recall_score(SLOW_ANSWER_SCORE));
+            if grade > 1 {
+                total -= predicted.ln();
+            } else {
+                total -= (1.0 - predicted).ln();
+            }
+            count += 1;
+
+            update(weights, &mut state, elapsed, grade);
+            state.last_review = result.time_asked;
+        }
+    }
+
+    if count > 0 {
+        total / (count as f64)
+    } else {
+        0.0
+    }
+}
+
+/// Update `state` for a review with the given grade, `elapsed` days after the last one.
+fn update(w: &[f64], state: &mut MemoryState, elapsed: f64, grade: u8) {
+    let r = forgetting_curve(elapsed, state.stability);
+    let d = state.difficulty;
+    let s = state.stability;
+    let stability = if grade == 1 {
+        w[11] * d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0) * (w[14] * (1.0 - r)).exp()
+    } else {
+        let hard_penalty = if grade == 2 { w[15] } else { 1.0 };
+        let easy_bonus = if grade == 4 { w[16] } else { 1.0 };
+        s * (1.0
+            + w[8].exp()
+                * (11.0 - d)
+                * s.powf(-w[9])
+                * ((w[10] * (1.0 - r)).exp() - 1.0)
+                * hard_penalty
+                * easy_bonus)
+    };
+    state.stability = stability.max(0.01);
+
+    let d = d - w[6] * (grade as f64 - 3.0);
+    // Mean reversion towards the initial difficulty of a "good" answer.
+    let d = w[7] * initial_difficulty(w, 3) + (1.0 - w[7]) * d;
+    state.difficulty = d.max(1.0).min(10.0);
+}
+
+/// Return the memory state of a question after it is asked for the first time.
+fn initial_state(w: &[f64], first: &QuestionResult) -> MemoryState {
+    let grade = score_to_grade(first.recall_score(SLOW_ANSWER_SCORE));
+    MemoryState {
+        stability: initial_stability(w, grade),
+        difficulty: initial_difficulty(w, grade),
+        last_review: first.time_asked,
+    }
+}
+
+fn initial_stability(w: &[f64], grade: u8) -> f64 {
+    w[(grade - 1) as usize].max(0.01)
+}
+
+fn initial_difficulty(w: &[f64], grade: u8) -> f64 {
+    (w[4] - w[5] * (grade as f64 - 3.0)).max(1.0).min(10.0)
+}
+
+fn forgetting_curve(elapsed: f64, stability: f64) -> f64 {
+    (1.0 + elapsed / (9.0 * stability)).powf(-1.0)
+}
+
+/// Convert a score out of 1,000 into an FSRS grade: 1 (again), 2 (hard), 3 (good) or 4
+/// (easy). drill has no notion of an easy answer, so a perfect score is a 3.
+fn score_to_grade(score: u64) -> u8 {
+    if score <= AGAIN_THRESHOLD {
+        1
+    } else if score < HARD_THRESHOLD {
+        2
+    } else {
+        3
+    }
+}
+
+fn days_between(
+    earlier: chrono::DateTime<chrono::Utc>,
+    later: chrono::DateTime<chrono::Utc>,
+) -> f64 {
+    let seconds = (later - earlier).num_seconds().max(0);
+    (seconds as f64) / 86400.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recall_decays_over_time() {
+        let parameters = Parameters::default();
+        let history = results(&[(0, 1000)]);
+        let state = memory_state(&parameters, &history).unwrap();
+        let start = history[0].time_asked;
+
+        let soon = retrievability(&state, start + chrono::Duration::hours(1));
+        let later = retrievability(&state, start + chrono::Duration::days(30));
+        assert!(soon > 0.99);
+        assert!(later < soon);
+        let at_stability = start + chrono::Duration::seconds((state.stability * 86400.0) as i64);
+        assert!((retrievability(&state, at_stability) - 0.9).abs() < 0.001);
+    }
+
+    #[test]
+    fn correct_answers_increase_stability() {
+        let parameters = Parameters::default();
+        let correct = memory_state(&parameters, &results(&[(0, 1000), (3, 1000)])).unwrap();
+        let incorrect = memory_state(&parameters, &results(&[(0, 1000), (3, 0)])).unwrap();
+        assert!(correct.stability > DEFAULT_WEIGHTS[2]);
+        assert!(incorrect.stability < correct.stability);
+        assert!(incorrect.difficulty > correct.difficulty);
+    }
+
+    #[test]
+    fn fitting_does_not_increase_loss() {
+        let history1 = results(&[(0, 1000), (1, 1000), (4, 0), (5, 1000), (12, 1000), (30, 0)]);
+        let history2 = results(&[(0, 0), (1, 0), (2, 1000), (6, 1000), (20, 1000), (50, 1000)]);
+        let all = vec![&history1, &history2];
+        let (_, before, after) = fit(&Parameters::default(), &all).unwrap();
+        assert!(after <= before);
+
+        let all = vec![&history1];
+        assert!(fit(&Parameters::default(), &all).is_none());
+    }
+
+    /// Construct a list of results from pairs of (days since start, score).
+    fn results(pairs: &[(i64, u64)]) -> Vec<QuestionResult> {
+        let start = chrono::Utc::now();
+        pairs
+            .iter()
+            .map(|(days, score)| {
+                QuestionResult::new("1", start + chrono::Duration::days(*days), *score)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,366 @@
+/**
+ * A journal of the session in progress, so that answers survive a crash.
+ *
+ * Results are normally only saved when a session finishes. While a session is in
+ * progress, every result is also appended to a journal file in the results directory
+ * as soon as it is recorded. Each session has a journal of its own, named after the
+ * session's ID, which is locked for as long as the session is in progress. The journal
+ * is deleted once the session's results have been saved, so a journal that is still
+ * there and that no other process has locked means that its session was interrupted,
+ * and it can be resumed with the questions that were chosen for it but not yet asked.
+ *
+ * The first line of the journal records the questions chosen for the session, the
+ * seed of its random number generator, and the rest of the session's record, so that a
+ * resumed session is saved as the same session. Each following line is a single result
+ * along with its position in the session. A result that is changed after it is
+ * recorded, e.g. by `!!`, is simply written again, and the last line for each position
+ * wins.
+ *
+ * If the process receives SIGTERM or SIGHUP, the results in the journal are saved
+ * before it exits.
+ *
+ * Author:  Ian Fisher (iafisher@fastmail.com)
+ * Version: October 2019
+ */
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{mem, process, ptr, thread};
+
+use serde::{Deserialize, Serialize};
+
+use super::common::{QuizError, Result};
+use super::persistence;
+use super::quiz::{QuestionResult, QuizResult, Session};
+
+// The path of the journal of the session in progress, which is taken when the session's
+// results are saved. Held while the journal is written to or its results are saved, so
+// that the signal handler never sees half of a line or saves the same results twice.
+static ACTIVE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A session that was interrupted before its results were saved.
+#[derive(Debug)]
+pub struct Interrupted {
+    /// The IDs of the questions that were chosen for the session, in order.
+    pub questions: Vec<String>,
+    pub seed: u64,
+    /// The session, or `None` if the journal was written by an older version.
+    pub session: Option<Session>,
+    /// The results recorded before the session was interrupted.
+    pub results: Vec<QuestionResult>,
+    path: PathBuf,
+    /// The journal file, locked so that no other process takes over the session too.
+    file: Option<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    questions: Vec<String>,
+    seed: u64,
+    #[serde(default)]
+    session: Option<Session>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// The position of the result in the session.
+    n: usize,
+    /// Needed because `QuestionResult` does not serialize its own `id` field.
+    id: String,
+    result: QuestionResult,
+}
+
+/// The journal of the session in progress.
+pub struct Journal {
+    /// The quiz that the session is of, or `None` if the session is not being saved.
+    fullname: Option<PathBuf>,
+    /// The journal file, locked for as long as the session is in progress.
+    file: Option<File>,
+    /// The session to resume, if any. Taken by `Quiz::take`.
+    interrupted: Option<Interrupted>,
+}
+
+impl Journal {
+    /// Return a journal for a new session of the quiz at `fullname`. The journal file
+    /// is not created until `start` is called.
+    pub fn new(fullname: &Path) -> Self {
+        Journal {
+            fullname: Some(fullname.to_path_buf()),
+            file: None,
+            interrupted: None,
+        }
+    }
+
+    /// Return a journal that records nothing, for sessions that are not saved.
+    pub fn disabled() -> Self {
+        Journal {
+            fullname: None,
+            file: None,
+            interrupted: None,
+        }
+    }
+
+    /// Return a journal that continues the interrupted session of the quiz at
+    /// `fullname`.
+    pub fn resume(fullname: &Path, mut interrupted: Interrupted) -> Self {
+        *ACTIVE.lock().unwrap() = Some(interrupted.path.clone());
+        Journal {
+            fullname: Some(fullname.to_path_buf()),
+            file: interrupted.file.take(),
+            interrupted: Some(interrupted),
+        }
+    }
+
+    /// Return the interrupted session that this journal continues, if any.
+    pub fn take_interrupted(&mut self) -> Option<Interrupted> {
+        self.interrupted.take()
+    }
+
+    /// Start the journal for a session with the given questions.
+    pub fn start(&mut self, questions: &[&str], session: &Session) -> Result<()> {
+        if let Some(fullname) = self.fullname.as_ref() {
+            let path = persistence::get_journal_path(fullname, &session.id)?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(QuizError::Io)?;
+            }
+
+            // The journal is locked and given its header under another name, so that
+            // other processes never find it unlocked or empty.
+            let mut tmp_path = path.as_os_str().to_os_string();
+            tmp_path.push(".tmp");
+            let file = File::create(&tmp_path).map_err(QuizError::Io)?;
+            if !try_lock(&file)? {
+                return Err(QuizError::CannotWriteToFile(path));
+            }
+            self.file = Some(file);
+            let header = Header {
+                questions: questions.iter().map(|id| String::from(*id)).collect(),
+                seed: session.seed,
+                session: Some(session.clone()),
+            };
+            self.write(&header)?;
+            fs::rename(&tmp_path, &path).map_err(QuizError::Io)?;
+            *ACTIVE.lock().unwrap() = Some(path);
+        }
+        Ok(())
+    }
+
+    /// Record `result`, the `n`th result of the session.
+    pub fn record(&mut self, n: usize, result: &QuestionResult) -> Result<()> {
+        let entry = Entry {
+            n,
+            id: result.id.clone(),
+            result: result.clone(),
+        };
+        self.write(&entry)
+    }
+
+    /// Save `results` as the results of the session and delete the journal.
+    pub fn finish(&mut self, fullname: &Path, results: &QuizResult) -> Result<()> {
+        if self.fullname.is_none() {
+            return Ok(());
+        }
+
+        let mut active = ACTIVE.lock().unwrap();
+        // The journal is deleted before it is unlocked, so that no other process
+        // mistakes it for an interrupted session.
+        if let Some(path) = active.take() {
+            save_and_remove(fullname, &path, results)?;
+        }
+        self.file = None;
+        Ok(())
+    }
+
+    fn write<T: Serialize>(&mut self, line: &T) -> Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            let _active = ACTIVE.lock().unwrap();
+            let mut line = serde_json::to_string(line).map_err(QuizError::Json)?;
+            line.push('\n');
+            file.write_all(line.as_bytes()).map_err(QuizError::Io)?;
+            file.sync_data().map_err(QuizError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return an interrupted session of the quiz at `fullname`, if there is one. Journals
+/// that another process has locked belong to sessions that are still in progress, and
+/// are skipped.
+pub fn load(fullname: &Path) -> Result<Option<Interrupted>> {
+    for path in persistence::list_journals(fullname)? {
+        let mut file = match fs::OpenOptions::new().read(true).append(true).open(&path) {
+            Ok(file) => file,
+            // The session finished after its journal was listed.
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(e) => {
+                return Err(QuizError::Io(e));
+            }
+        };
+        // The session may also have finished between opening the journal and locking
+        // it, in which case the journal is gone and its results have been saved.
+        if !try_lock(&file)? || !path.exists() {
+            continue;
+        }
+
+        let mut data = String::new();
+        file.read_to_string(&mut data).map_err(QuizError::Io)?;
+        let mut interrupted = match parse(&path, &data) {
+            Some(interrupted) => interrupted,
+            None => {
+                // The session was interrupted before anything was written.
+                fs::remove_file(&path).map_err(QuizError::Io)?;
+                continue;
+            }
+        };
+
+        if is_saved(fullname, &interrupted)? {
+            // The session was interrupted after its results were saved but before its
+            // journal was deleted.
+            fs::remove_file(&path).map_err(QuizError::Io)?;
+            continue;
+        }
+
+        interrupted.file = Some(file);
+        return Ok(Some(interrupted));
+    }
+    Ok(None)
+}
+
+/// Save the results of `interrupted`, a session of the quiz at `fullname`, and delete
+/// its journal.
+pub fn flush(fullname: &Path, interrupted: Interrupted) -> Result<()> {
+    let results = interrupted_results(interrupted.results, interrupted.session);
+    save_and_remove(fullname, &interrupted.path, &results)
+}
+
+fn parse(path: &Path, data: &str) -> Option<Interrupted> {
+    let mut lines = data.lines();
+    let header: Header = serde_json::from_str(lines.next()?).ok()?;
+
+    let mut results: Vec<QuestionResult> = Vec::new();
+    for line in lines {
+        // The last line may have been cut off if the process was killed while
+        // writing it.
+        if let Ok(entry) = serde_json::from_str::<Entry>(line) {
+            let mut result = entry.result;
+            result.id = entry.id;
+            if entry.n < results.len() {
+                results[entry.n] = result;
+            } else {
+                results.push(result);
+            }
+        }
+    }
+
+    Some(Interrupted {
+        questions: header.questions,
+        seed: header.seed,
+        session: header.session,
+        results,
+        path: path.to_path_buf(),
+        file: None,
+    })
+}
+
+/// Return whether the results of `interrupted` have already been saved.
+fn is_saved(fullname: &Path, interrupted: &Interrupted) -> Result<bool> {
+    let id = match (&interrupted.session, interrupted.results.first()) {
+        (Some(session), Some(_)) => &session.id,
+        _ => {
+            return Ok(false);
+        }
+    };
+    let saved = persistence::load_results(fullname)?;
+    Ok(saved
+        .values()
+        .flatten()
+        .any(|r| r.session.as_ref() == Some(id)))
+}
+
+fn interrupted_results(results: Vec<QuestionResult>, session: Option<Session>) -> QuizResult {
+    let time_finished = results
+        .last()
+        .map(|r| r.time_asked)
+        .unwrap_or_else(chrono::Utc::now);
+    QuizResult::from_results(results, session, time_finished)
+}
+
+fn save_and_remove(fullname: &Path, path: &Path, results: &QuizResult) -> Result<()> {
+    if results.total > 0 {
+        persistence::save_results(fullname, results)?;
+    }
+    if path.exists() {
+        fs::remove_file(path).map_err(QuizError::Io)?;
+    }
+    Ok(())
+}
+
+/// Lock `file` without waiting, and return whether the lock was taken.
+fn try_lock(file: &File) -> Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        Ok(true)
+    } else {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+            Ok(false)
+        } else {
+            Err(QuizError::Io(error))
+        }
+    }
+}
+
+/// Save the journal of the session of the quiz at `fullname` and exit if the process
+/// receives SIGTERM or SIGHUP. Must be called before any other threads are started.
+pub fn flush_on_signal(fullname: &Path) {
+    // The signals are blocked in every thread and waited for in a thread of their own,
+    // where it is safe to do more than set a flag.
+    let mut signals: libc::sigset_t = unsafe { mem::zeroed() };
+    // The terminal may be in raw mode when the signal arrives, if the main thread is
+    // waiting for an answer.
+    let mut terminal: libc::termios = unsafe { mem::zeroed() };
+    let has_terminal = unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigaddset(&mut signals, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+        libc::tcgetattr(libc::STDIN_FILENO, &mut terminal) == 0
+    };
+
+    let fullname = fullname.to_path_buf();
+    thread::spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            return;
+        }
+
+        // The lock is never released, so that the main thread cannot go on to save the
+        // same results or write to the journal after it is deleted.
+        let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(path) = active.take() {
+            if let Err(e) = flush_path(&fullname, &path) {
+                eprintln!("Error: could not save results ({})", e);
+            }
+        }
+        if has_terminal {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &terminal);
+            }
+        }
+        process::exit(128 + signal);
+    });
+}
+
+fn flush_path(fullname: &Path, path: &Path) -> Result<()> {
+    let data = fs::read_to_string(path).map_err(QuizError::Io)?;
+    if let Some(interrupted) = parse(path, &data) {
+        flush(fullname, interrupted)
+    } else {
+        fs::remove_file(path).map_err(QuizError::Io)
+    }
+}
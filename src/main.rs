@@ -5,27 +5,33 @@
  * Version: October 2019
  */
 mod common;
+mod fsrs;
 #[macro_use]
 mod iohelper;
+mod forecast;
+mod journal;
 mod persistence;
 mod persistence2;
 mod quiz;
 mod quiz2;
 mod repetition;
 mod repetition2;
+mod simulate;
 mod ui;
 mod ui2;
 
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::env;
 use std::io;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use colored::*;
 
-use common::{Command, Options, QuizError, Result};
-use iohelper::prettyprint_colored;
+use common::{Clock, Command, Options, QuizError, Result};
+use iohelper::{prettyprint, prettyprint_colored};
+use journal::Journal;
 use quiz::QuestionResult;
 use ui::CmdUI;
 
@@ -44,7 +50,13 @@ fn main_v2() {
     }
 
     let result = match options.cmd {
+        Command::ConvertResults(options) => main_convert_results(&options),
+        Command::Fit(options) => main_fit(&options),
+        Command::Forecast(options) => main_forecast(&options, &common::SystemClock),
+        Command::MergeResults(options) => main_merge_results(&options),
+        Command::Migrate(options) => main_migrate(&options),
         Command::Results(options) => main_results_v2(&options),
+        Command::Simulate(options) => main_simulate(&options, &common::SystemClock),
         Command::Take(options) => main_take_v2(&options),
     };
 
@@ -65,9 +77,9 @@ fn main_take_v2(options: &common::TakeOptions) -> Result<()> {
     let mut ui = ui2::CmdUI::new();
     let results = quiz.take(&mut ui, &options)?;
 
-    // if results.total > 0 && !options.no_save {
-    //     persistence::save_results(&options.name, &results)?;
-    // }
+    if results.total > 0 && !options.no_save {
+        persistence2::save_results(&options.name, &results)?;
+    }
     Ok(())
 }
 
@@ -84,9 +96,21 @@ fn main() {
         }
     }
 
+    let clock: Box<dyn Clock> = if let Some(now) = options.now {
+        Box::new(common::OffsetClock::new(now))
+    } else {
+        Box::new(common::SystemClock)
+    };
+
     let result = match options.cmd {
+        Command::ConvertResults(options) => main_convert_results(&options),
+        Command::Fit(options) => main_fit(&options),
+        Command::Forecast(options) => main_forecast(&options, clock.as_ref()),
+        Command::MergeResults(options) => main_merge_results(&options),
+        Command::Migrate(options) => main_migrate(&options),
         Command::Results(options) => main_results(&options),
-        Command::Take(options) => main_take(&options),
+        Command::Simulate(options) => main_simulate(&options, clock.as_ref()),
+        Command::Take(options) => main_take(&options, clock.as_ref()),
     };
 
     if let Err(e) = result {
@@ -97,6 +121,133 @@ fn main() {
     }
 }
 
+/// The main function for the `convert-results` subcommand.
+pub fn main_convert_results(options: &common::ConvertOptions) -> Result<()> {
+    // Make sure that the quiz exists before touching its results.
+    persistence::load_quiz(&options.name)?;
+    let count = persistence::convert_results(&options.name, options.to)?;
+    my_println!(
+        "Converted {} result(s) to the {} format.",
+        count,
+        options.to.name()
+    )
+}
+
+/// The main function for the `fit` subcommand.
+pub fn main_fit(options: &common::FitOptions) -> Result<()> {
+    let quiz = persistence::load_quiz(&options.name)?;
+    let histories: Vec<&Vec<QuestionResult>> = quiz
+        .questions
+        .iter()
+        .map(|q| &q.get_common().prior_results)
+        .collect();
+
+    let nreviews = fsrs::count_reviews(&histories);
+    if let Some((parameters, before, after)) = fsrs::fit(&quiz.fsrs_parameters, &histories) {
+        persistence::save_fsrs_parameters(&options.name, &parameters)?;
+        my_println!(
+            "Fitted memory model to {} reviews (log loss {:.4} -> {:.4}).",
+            nreviews,
+            before,
+            after
+        )?;
+        Ok(())
+    } else {
+        Err(QuizError::NotEnoughHistory {
+            needed: fsrs::MINIMUM_REVIEWS_TO_FIT,
+            found: nreviews,
+        })
+    }
+}
+
+/// The main function for the `forecast` subcommand.
+pub fn main_forecast(options: &common::ForecastOptions, clock: &dyn Clock) -> Result<()> {
+    let names = if options.all {
+        persistence::list_quizzes(Path::new("."))?
+    } else {
+        vec![options.name.clone()]
+    };
+
+    let mut forecast = forecast::Forecast::new(clock.now(), options.days);
+    for name in names.iter() {
+        let shortname = name.strip_prefix(".").unwrap_or(name);
+        let quiz = match persistence::load_quiz(name) {
+            Ok(quiz) => quiz,
+            // With --all, any file without an extension is taken to be a quiz, so files
+            // like Makefile that are not quizzes are skipped.
+            Err(e @ QuizError::Parse { .. }) if options.all => {
+                eprintln!(
+                    "{}: skipping '{}', which could not be parsed as a quiz ({})",
+                    "Warning".yellow(),
+                    shortname.to_string_lossy(),
+                    e
+                );
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        forecast.add(&shortname.to_string_lossy(), &quiz, options.scheduler);
+    }
+
+    if options.json {
+        forecast::print_json(&forecast)
+    } else {
+        forecast::print_chart(&forecast)
+    }
+}
+
+/// The main function for the `merge-results` subcommand.
+pub fn main_merge_results(options: &common::MergeOptions) -> Result<()> {
+    let quiz = persistence::load_quiz(&options.name)?;
+    let summary = persistence::merge_results(&options.name, &options.paths)?;
+    my_println!(
+        "Merged {} new result(s) from {} file(s), skipping {} duplicate(s).",
+        summary.added,
+        options.paths.len(),
+        summary.duplicates
+    )?;
+    if summary.sessions > 0 {
+        my_println!("Merged {} new session(s).", summary.sessions)?;
+    }
+
+    let unknown: Vec<(&String, &usize)> = summary
+        .counts
+        .iter()
+        .filter(|(id, _)| !quiz.questions.iter().any(|q| &q.get_common().id == *id))
+        .collect();
+    if unknown.len() > 0 {
+        my_println!("\nResults for questions that are no longer in the quiz:")?;
+        for (id, count) in unknown.iter() {
+            my_println!("  [{}] {} result(s)", id, count)?;
+        }
+    }
+    Ok(())
+}
+
+/// The main function for the `migrate` subcommand.
+pub fn main_migrate(options: &common::MigrateOptions) -> Result<()> {
+    if options.output.exists() {
+        return Err(QuizError::FileAlreadyExists(options.output.clone()));
+    }
+
+    let result = persistence2::migrate_quiz(&options.name, &options.output);
+    if result.is_err() {
+        // Don't leave a partially migrated database behind.
+        let _ = std::fs::remove_file(&options.output);
+    }
+    let (nquestions, nresults) = result?;
+
+    my_println!(
+        "Migrated {} question(s) and {} result(s) to {}.",
+        nquestions,
+        nresults,
+        options.output.to_string_lossy()
+    )?;
+    Ok(())
+}
+
 /// The main function for the `results` subcommand.
 pub fn main_results(options: &common::ResultsOptions) -> Result<()> {
     let quiz = persistence::load_quiz(&options.name)?;
@@ -109,6 +260,11 @@ pub fn main_results(options: &common::ResultsOptions) -> Result<()> {
 
     let mut aggregated: Vec<(u64, usize, String, String)> = Vec::new();
     for (key, result) in results.iter() {
+        let result: Vec<QuestionResult> = result
+            .iter()
+            .filter(|r| r.practice != Some(true))
+            .cloned()
+            .collect();
         // Only include questions that have scored results.
         if let Some(score) = results_mean(&result) {
             if let Some(pos) = quiz.find(key) {
@@ -132,28 +288,195 @@ pub fn main_results(options: &common::ResultsOptions) -> Result<()> {
         )?;
     }
 
+    // Group the answers by the day they were given on, so that the user can see how
+    // their recent sessions went.
+    let mut days: BTreeMap<chrono::NaiveDate, Vec<QuestionResult>> = BTreeMap::new();
+    for result in results.values() {
+        for r in result.iter().filter(|r| r.practice != Some(true)) {
+            days.entry(quiz.calendar.day_of(r.time_asked))
+                .or_insert_with(Vec::new)
+                .push(r.clone());
+        }
+    }
+    if days.len() > 0 {
+        my_println!("\nHistory:")?;
+        let skip = days.len().saturating_sub(HISTORY_DAYS);
+        for (day, results) in days.iter().skip(skip) {
+            let score = quiz::score_to_perc(results_mean(results).unwrap_or(0)) * 100.0;
+            my_println!(
+                "  {}   {:>5.1}% of {:>2}",
+                day.format("%a %b %e %Y"),
+                score,
+                results.len()
+            )?;
+        }
+    }
+
+    let sessions = persistence::load_sessions(&options.name)?;
+    if sessions.len() > 0 {
+        my_println!("\nSessions:")?;
+        let skip = sessions.len().saturating_sub(SESSION_COUNT);
+        for record in sessions.iter().skip(skip) {
+            let score = quiz::score_to_perc(record.score) * 100.0;
+            let duration = record.time_finished - record.session.time_started;
+            my_println!(
+                "  {}   {:>5.1}% of {:>2}   {}m {:02}s",
+                quiz.calendar
+                    .day_of(record.session.time_started)
+                    .format("%a %b %e %Y"),
+                score,
+                record.total,
+                duration.num_minutes(),
+                duration.num_seconds() % 60
+            )?;
+        }
+    }
+
+    let mut slowest: Vec<(u64, String, String)> = Vec::new();
+    for (key, result) in results.iter() {
+        let times: Vec<u64> = result
+            .iter()
+            .filter(|r| r.practice != Some(true))
+            .filter_map(|r| r.response_time_ms)
+            .collect();
+        if times.len() > 0 {
+            if let Some(pos) = quiz.find(key) {
+                let mean = times.iter().sum::<u64>() / times.len() as u64;
+                let text = quiz.questions[pos].get_text();
+                slowest.push((mean, key.clone(), text));
+            }
+        }
+    }
+    if slowest.len() > 0 {
+        // Compare the times as they are displayed, to the nearest tenth of a second, so
+        // that questions that appear to be equally slow are listed in order of ID.
+        slowest.sort_by_key(|(mean, id, _)| (std::cmp::Reverse((mean + 50) / 100), id.clone()));
+        my_println!("\nSlowest:")?;
+        for (mean, id, text) in slowest.iter().take(SLOWEST_COUNT) {
+            let first_prefix = format!("{:>6.1}s   ", (*mean as f64) / 1000.0);
+            prettyprint_colored(
+                &format!("[{}] {}", id, text),
+                &first_prefix,
+                None,
+                Some(Color::Cyan),
+            )?;
+        }
+    }
+
+    let leeches: Vec<(usize, &Box<dyn quiz::Question>)> = quiz
+        .questions
+        .iter()
+        .map(|q| (repetition::count_lapses(q.as_ref()), q))
+        .filter(|(lapses, _)| *lapses >= quiz.leech_threshold)
+        .collect();
+    if leeches.len() > 0 {
+        if quiz.suspend_leeches {
+            my_println!("\nLeeches (suspended until rewritten):")?;
+        } else {
+            my_println!("\nLeeches:")?;
+        }
+
+        for (lapses, q) in leeches.iter() {
+            let first_prefix = format!("{:>3} lapses   ", lapses);
+            prettyprint_colored(
+                &format!("[{}] {}", q.get_common().id, q.get_text()),
+                &first_prefix,
+                None,
+                Some(Color::Cyan),
+            )?;
+        }
+    }
+
+    let mut printed_header = false;
+    for q in quiz.questions.iter() {
+        let notes: Vec<&String> = q
+            .get_common()
+            .prior_results
+            .iter()
+            .filter_map(|r| r.flag.as_ref())
+            .collect();
+        if notes.len() == 0 {
+            continue;
+        }
+
+        if !printed_header {
+            my_println!("\nFlagged:")?;
+            printed_header = true;
+        }
+        prettyprint_colored(
+            &format!("[{}] {}", q.get_common().id, q.get_text()),
+            "  ",
+            None,
+            Some(Color::Cyan),
+        )?;
+        for note in notes.iter().filter(|note| note.len() > 0) {
+            prettyprint(note, "    - ")?;
+        }
+    }
+
     Ok(())
 }
 
+/// The main function for the `simulate` subcommand.
+pub fn main_simulate(options: &common::SimulateOptions, clock: &dyn Clock) -> Result<()> {
+    let mut quiz = persistence::load_quiz(&options.take.name)?;
+    let result = simulate::simulate(&mut quiz, &options, clock.now());
+    simulate::print_report(&quiz, &options, &result)
+}
+
 /// The main function for the `take` subcommand.
-pub fn main_take(options: &common::TakeOptions) -> Result<()> {
+pub fn main_take(options: &common::TakeOptions, clock: &dyn Clock) -> Result<()> {
     let mut quiz = persistence::load_quiz(&options.name)?;
     let mut ui = CmdUI::new();
-    let results = quiz.take(&mut ui, &options)?;
-
-    if results.total > 0 && !options.no_save {
-        persistence::save_results(&options.name, &results)?;
+    if options.no_save {
+        quiz.take(&mut ui, &options, clock, &mut Journal::disabled())?;
+        return Ok(());
     }
-    Ok(())
+
+    let mut journal = match journal::load(&options.name)? {
+        Some(interrupted) => {
+            if options.resume || ui.confirm_resume()? {
+                Journal::resume(&options.name, interrupted)
+            } else {
+                // Keep the answers that were given before the session was interrupted.
+                journal::flush(&options.name, interrupted)?;
+                Journal::new(&options.name)
+            }
+        }
+        None => {
+            if options.resume {
+                return Err(QuizError::NothingToResume);
+            }
+            Journal::new(&options.name)
+        }
+    };
+    journal::flush_on_signal(&options.name);
+
+    let results = quiz.take(&mut ui, &options, clock, &mut journal)?;
+    journal.finish(&options.name, &results)
 }
 
 /// Parse command-line arguments.
 fn parse_options() -> common::Options {
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
+
+    // `--now` is a hidden option, for testing, that may appear anywhere.
+    let mut now = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--now") {
+        cmd_assert_next(&args, i);
+        if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(&args[i + 1]) {
+            now = Some(timestamp.with_timezone(&chrono::Utc));
+        } else {
+            cmd_error("Expected RFC 3339 timestamp argument to --now.");
+        }
+        args.drain(i..i + 2);
+    }
+
     if args.len() == 0 {
         return Options {
             no_color: false,
+            now,
             cmd: common::Command::Take(parse_take_options(&Vec::new())),
         };
     }
@@ -164,15 +487,61 @@ fn parse_options() -> common::Options {
     }
 
     match args[0].as_str() {
+        "--convert-results" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::ConvertResults(parse_convert_options(&args)),
+            };
+        }
+        "--fit" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::Fit(common::FitOptions {
+                    name: parse_results_options(&args).name,
+                }),
+            };
+        }
+        "--forecast" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::Forecast(parse_forecast_options(&args)),
+            };
+        }
+        "--merge-results" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::MergeResults(parse_merge_options(&args)),
+            };
+        }
+        "--migrate" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::Migrate(parse_migrate_options(&args)),
+            };
+        }
         "--results" => {
             return Options {
                 no_color,
+                now,
                 cmd: common::Command::Results(parse_results_options(&args)),
             };
         }
+        "--simulate" => {
+            return Options {
+                no_color,
+                now,
+                cmd: common::Command::Simulate(parse_simulate_options(&args)),
+            };
+        }
         "--take" => {
             return Options {
                 no_color,
+                now,
                 cmd: common::Command::Take(parse_take_options(&args)),
             };
         }
@@ -183,12 +552,149 @@ fn parse_options() -> common::Options {
         _ => {
             return Options {
                 no_color,
+                now,
                 cmd: common::Command::Take(parse_take_options(&args)),
             };
         }
     }
 }
 
+fn parse_convert_options(args: &Vec<String>) -> common::ConvertOptions {
+    let mut name = None;
+    let mut to = common::ResultsFormat::Jsonl;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--to" {
+            cmd_assert_next(args, i);
+            if let Some(format) = common::ResultsFormat::from_name(&args[i + 1]) {
+                to = format;
+            } else {
+                cmd_error(&format!("Unknown results format '{}'.", args[i + 1]));
+            }
+            i += 2;
+        } else if args[i].starts_with("-") {
+            cmd_error_unexpected_option(&args[i]);
+        } else {
+            if name.is_some() {
+                cmd_error(&format!("Unexpected positional argument '{}'.", args[i]));
+            } else {
+                name.replace(PathBuf::from(&args[i]));
+            }
+            i += 1;
+        }
+    }
+
+    common::ConvertOptions {
+        name: name.unwrap_or(PathBuf::from("main")),
+        to,
+    }
+}
+
+fn parse_merge_options(args: &Vec<String>) -> common::MergeOptions {
+    let mut positional = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        if args[i].starts_with("-") {
+            cmd_error_unexpected_option(&args[i]);
+        } else {
+            positional.push(PathBuf::from(&args[i]));
+            i += 1;
+        }
+    }
+
+    if positional.len() < 2 {
+        cmd_error("Expected a quiz and at least one results file to merge.");
+    }
+    let name = positional.remove(0);
+    common::MergeOptions {
+        name,
+        paths: positional,
+    }
+}
+
+fn parse_migrate_options(args: &Vec<String>) -> common::MigrateOptions {
+    let mut positional = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        if args[i].starts_with("-") {
+            cmd_error_unexpected_option(&args[i]);
+        } else {
+            if positional.len() == 2 {
+                cmd_error(&format!("Unexpected positional argument '{}'.", args[i]));
+            } else {
+                positional.push(PathBuf::from(&args[i]));
+            }
+            i += 1;
+        }
+    }
+
+    let name = if positional.len() > 0 {
+        positional.remove(0)
+    } else {
+        PathBuf::from("main")
+    };
+    let output = if positional.len() > 0 {
+        positional.remove(0)
+    } else {
+        let mut output = name.clone().into_os_string();
+        output.push(".db");
+        PathBuf::from(output)
+    };
+
+    common::MigrateOptions { name, output }
+}
+
+fn parse_forecast_options(args: &Vec<String>) -> common::ForecastOptions {
+    let mut name = None;
+    let mut all = false;
+    let mut days = 30;
+    let mut json = false;
+    let mut scheduler = None;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--all" {
+            all = true;
+            i += 1;
+        } else if args[i] == "--days" {
+            cmd_assert_next(args, i);
+            days = parse_number(&args[i], &args[i + 1]);
+            i += 2;
+        } else if args[i] == "--json" {
+            json = true;
+            i += 1;
+        } else if args[i] == "--scheduler" {
+            cmd_assert_next(args, i);
+            if let Some(kind) = common::SchedulerKind::from_name(&args[i + 1]) {
+                scheduler = Some(kind);
+            } else {
+                cmd_error(&format!("Unknown scheduler '{}'.", args[i + 1]));
+            }
+            i += 2;
+        } else if args[i].starts_with("-") {
+            cmd_error_unexpected_option(&args[i]);
+        } else {
+            if name.is_some() {
+                cmd_error(&format!("Unexpected positional argument '{}'.", args[i]));
+            } else {
+                name.replace(PathBuf::from(&args[i]));
+            }
+            i += 1;
+        }
+    }
+
+    if all && name.is_some() {
+        cmd_error("Cannot give a quiz name with --all.");
+    }
+
+    common::ForecastOptions {
+        name: name.unwrap_or(PathBuf::from("main")),
+        all,
+        days,
+        json,
+        scheduler,
+    }
+}
+
 fn parse_results_options(args: &Vec<String>) -> common::ResultsOptions {
     let mut name = None;
     let mut i = 1;
@@ -210,21 +716,77 @@ fn parse_results_options(args: &Vec<String>) -> common::ResultsOptions {
     }
 }
 
+fn parse_simulate_options(args: &Vec<String>) -> common::SimulateOptions {
+    let mut days = 30;
+    let mut seed = 0;
+    let mut initial_strength = 1.0;
+    let mut growth = 2.5;
+    let mut replay = false;
+    // Options that are not specific to the simulation are passed on to
+    // `parse_take_options`.
+    let mut take_args = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--days" {
+            cmd_assert_next(args, i);
+            days = parse_number(&args[i], &args[i + 1]);
+            i += 2;
+        } else if args[i] == "--seed" {
+            cmd_assert_next(args, i);
+            seed = parse_number(&args[i], &args[i + 1]);
+            i += 2;
+        } else if args[i] == "--initial-strength" {
+            cmd_assert_next(args, i);
+            initial_strength = parse_number(&args[i], &args[i + 1]);
+            i += 2;
+        } else if args[i] == "--growth" {
+            cmd_assert_next(args, i);
+            growth = parse_number(&args[i], &args[i + 1]);
+            i += 2;
+        } else if args[i] == "--replay" {
+            replay = true;
+            i += 1;
+        } else {
+            take_args.push(args[i].clone());
+            i += 1;
+        }
+    }
+
+    common::SimulateOptions {
+        take: parse_take_options(&take_args),
+        days,
+        seed,
+        initial_strength,
+        growth,
+        replay,
+    }
+}
+
 fn parse_take_options(args: &Vec<String>) -> common::TakeOptions {
     let mut name = None;
+    let mut due = false;
     let mut flip = false;
     let mut in_order = false;
     let mut no_save = false;
     let mut num_to_ask = 20;
     let mut exclude = Vec::new();
     let mut tags = Vec::new();
+    let mut scheduler = None;
+    let mut relearn = false;
+    let mut cram = None;
+    let mut save = false;
+    let mut seed = None;
+    let mut resume = false;
     let mut i = if args.len() > 0 && args[0] == "--take" {
         1
     } else {
         0
     };
     while i < args.len() {
-        if args[i] == "--flip" {
+        if args[i] == "--due" {
+            due = true;
+            i += 1;
+        } else if args[i] == "--flip" {
             flip = true;
             i += 1;
         } else if args[i] == "--in-order" {
@@ -241,10 +803,42 @@ fn parse_take_options(args: &Vec<String>) -> common::TakeOptions {
         } else if args[i] == "--no-save" {
             no_save = true;
             i += 1;
+        } else if args[i] == "--relearn" {
+            relearn = true;
+            i += 1;
+        } else if args[i] == "--cram" {
+            cmd_assert_next(args, i);
+            match usize::from_str_radix(&args[i + 1], 10) {
+                Ok(n) if n > 0 => {
+                    cram = Some(n);
+                }
+                _ => {
+                    cmd_error("Expected positive integer argument to --cram.");
+                }
+            }
+            i += 2;
+        } else if args[i] == "--resume" {
+            resume = true;
+            i += 1;
+        } else if args[i] == "--save" {
+            save = true;
+            i += 1;
+        } else if args[i] == "--seed" {
+            cmd_assert_next(args, i);
+            seed = Some(parse_number(&args[i], &args[i + 1]));
+            i += 2;
         } else if args[i] == "--tag" {
             cmd_assert_next(args, i);
             tags.push(args[i + 1].clone());
             i += 2;
+        } else if args[i] == "--scheduler" {
+            cmd_assert_next(args, i);
+            if let Some(kind) = common::SchedulerKind::from_name(&args[i + 1]) {
+                scheduler = Some(kind);
+            } else {
+                cmd_error(&format!("Unknown scheduler '{}'.", args[i + 1]));
+            }
+            i += 2;
         } else if args[i] == "--exclude" {
             cmd_assert_next(args, i);
             exclude.push(args[i + 1].clone());
@@ -261,6 +855,17 @@ fn parse_take_options(args: &Vec<String>) -> common::TakeOptions {
         }
     }
 
+    if save && cram.is_none() {
+        cmd_error("--save can only be used with --cram.");
+    }
+
+    // Cram sessions are not saved by default so that they don't disturb the long-term
+    // schedule.
+    let no_save = no_save || (cram.is_some() && !save);
+    if resume && no_save {
+        cmd_error("--resume cannot be used when results are not saved.");
+    }
+
     common::TakeOptions {
         name: name.unwrap_or(PathBuf::from("main")),
         flip,
@@ -268,6 +873,20 @@ fn parse_take_options(args: &Vec<String>) -> common::TakeOptions {
         no_save,
         num_to_ask,
         filter_opts: common::FilterOptions { exclude, tags },
+        scheduler,
+        due,
+        relearn,
+        cram,
+        seed,
+        resume,
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> T {
+    if let Ok(n) = value.parse() {
+        n
+    } else {
+        cmd_error(&format!("Expected numeric argument to {}.", option));
     }
 }
 
@@ -336,6 +955,13 @@ fn is_broken_pipe(e: &QuizError) -> bool {
     false
 }
 
+// How many of the most recent days with answers `--results` shows.
+const HISTORY_DAYS: usize = 7;
+// How many of the questions that took the longest to answer `--results` shows.
+const SLOWEST_COUNT: usize = 5;
+// How many of the most recent sessions `--results` shows.
+const SESSION_COUNT: usize = 5;
+
 fn results_mean(results: &Vec<QuestionResult>) -> Option<u64> {
     if results.len() > 0 {
         // Tried to do this with iterators but Rust's type checker couldn't handle it.
@@ -354,6 +980,12 @@ const HELP: &'static str = r"drill: quiz yourself from the command line.
 Usage:
   drill <quiz>
   drill --results <quiz>
+  drill --migrate <quiz> [<database>]
+  drill --fit <quiz>
+  drill --simulate <quiz>
+  drill --forecast [<quiz> | --all]
+  drill --convert-results [<quiz>] [--to json|jsonl]
+  drill --merge-results <quiz> <results-file>...
   drill --help
 
 If <quiz> is not provided, it defaults to 'main' as long as the subcommand
@@ -361,14 +993,85 @@ requires no other positional argments.
 
 
 take subcommand:
+  --cram <N>         Ask every question (or every question with the given
+                     tags) until each has been answered correctly N times in
+                     a row, ignoring the schedule. Results are not saved
+                     unless --save is also given.
+  --due              Only ask questions that are due for review, even if
+                     there are fewer than N.
   --exclude <tag>    Exclude all questions with given tag.
   --flip             Flip all flashcards in the quiz.
   --in-order         Ask questions in the order they appear in the quiz file.
   -n <N>             Number of questions to ask. Defaults to 20.
   --no-save          Don't save results for this session.
+  --relearn          Ask missed questions again later in the session until
+                     they are answered correctly. Only the first answer to
+                     each question counts towards its score.
+  --resume           Resume the last session if it was interrupted before
+                     its results were saved, without asking first.
+  --save             Save results for a --cram session.
+  --seed <N>         Seed for the random number generator, to repeat the
+                     order and choices of an earlier session. The seed of
+                     each session is saved with its results.
+  --scheduler <name> Algorithm for choosing questions: 'buckets', 'sm2' or
+                     'fsrs'. Overrides the quiz's scheduler setting.
   --tag <tag>        Include only questions with given tag.
 
 
 results subcommand:
   <no special options>
+
+
+fit subcommand:
+  Fit the parameters of the 'fsrs' scheduler to the quiz's results. The
+  parameters are saved in the results directory.
+
+
+simulate subcommand:
+  Simulate daily sessions against a synthetic learner and report how well
+  the scheduler works. Accepts the options of the take subcommand, as well as:
+
+  --days <N>                Number of days to simulate. Defaults to 30.
+  --growth <F>              Factor by which the learner's memory of a question
+                            grows after a correct answer. Defaults to 2.5.
+  --initial-strength <D>    Days until the learner is likely to forget a new
+                            question. Defaults to 1.
+  --replay                  Start from the quiz's real results.
+  --seed <N>                Seed for the random number generator. Defaults
+                            to 0.
+
+
+forecast subcommand:
+  Show how many questions will fall due for review on each of the coming days,
+  based on the results recorded so far.
+
+  --all                 Forecast every quiz in the current directory (or in
+                        $DRILL_HOME, if set) instead of a single quiz.
+  --days <N>            Number of days to forecast. Defaults to 30.
+  --json                Print the forecast as JSON instead of a chart.
+  --scheduler <name>    Overrides the quiz's scheduler setting.
+
+
+migrate subcommand:
+  Convert <quiz> and its results into a SQLite database. The database is
+  written to <quiz>.db unless <database> is given, and must not already exist.
+
+
+convert-results subcommand:
+  Move the quiz's results into a single file of the given format, and delete
+  the file of the other format. New results are appended to whichever file
+  exists.
+
+  --to <format>     'jsonl' (the default) for a log with one line per result,
+                    which is only ever appended to and so merges cleanly
+                    under version control, or 'json' for a single object
+                    mapping question IDs to their results.
+
+
+merge-results subcommand:
+  Add the results in each <results-file> to the quiz's results, e.g. to
+  combine the results recorded on different machines. Files ending in
+  '.jsonl' are read as results logs, and all others as JSON results files.
+  Results that were already recorded are skipped, and results for questions
+  that are no longer in the quiz are kept but reported.
 ";
@@ -8,46 +8,140 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use super::common::{Location, QuizError, Result};
+use colored::*;
+use serde::{Deserialize, Serialize};
+
+use super::common::{Location, QuizError, Result, ResultsFormat, SchedulerKind};
+use super::fsrs;
 use super::quiz::{
     Answer, FlashcardQuestion, ListQuestion, MultipleChoiceQuestion, OrderedListQuestion, Question,
-    QuestionCommon, QuestionResult, Quiz, QuizResult, ShortAnswerQuestion,
+    QuestionCommon, QuestionResult, Quiz, QuizResult, Session, ShortAnswerQuestion,
 };
+use super::repetition;
 
 /// Load a `Quiz` object given its name.
 pub fn load_quiz(fullname: &Path) -> Result<Quiz> {
     let old_results = load_results(fullname)?;
-    parse(fullname, &old_results)
+    let mut quiz = parse(fullname, &old_results)?;
+    quiz.fsrs_parameters = load_fsrs_parameters(fullname)?;
+    Ok(quiz)
 }
 
 type StoredResults = HashMap<String, Vec<QuestionResult>>;
-type ChoiceGroup = HashMap<String, Answer>;
+// The files that a truncated line has been reported in, since the same file may be read
+// more than once by a single command.
+static WARNED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+// A `BTreeMap` so that the choices are always listed in the same order, which keeps
+// sessions reproducible with `--seed`.
+type ChoiceGroup = BTreeMap<String, Answer>;
 
+/// Load the results of a quiz. Results may be stored in a JSON file, which holds a map
+/// from question IDs to lists of results, or in a results log, which holds one result
+/// per line and is only ever appended to. If both files exist, their results are
+/// combined.
 pub fn load_results(fullname: &Path) -> Result<StoredResults> {
     let results_path = get_results_path(fullname)?;
-    match fs::read_to_string(results_path) {
-        Ok(data) => serde_json::from_str(&data).map_err(QuizError::Json),
-        Err(_) => Ok(HashMap::new()),
+    let mut results: StoredResults = match fs::read_to_string(&results_path) {
+        Ok(data) => parse_results(&results_path, &data)?,
+        Err(_) => HashMap::new(),
+    };
+
+    let log_path = get_results_log_path(fullname)?;
+    if let Ok(data) = fs::read_to_string(&log_path) {
+        for entry in parse_results_log(&log_path, &data)? {
+            let mut result = entry.result;
+            result.id = entry.id.clone();
+            results
+                .entry(entry.id)
+                .or_insert_with(Vec::new)
+                .push(result);
+        }
+        for list in results.values_mut() {
+            list.sort_by_key(|r| r.time_asked);
+        }
     }
+    Ok(results)
+}
+
+/// A line of a results log.
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    /// Needed because `QuestionResult` does not serialize its own `id` field.
+    id: String,
+    /// The session in which the result was recorded, in logs written before the session
+    /// was stored in the result itself.
+    #[serde(default, skip_serializing)]
+    session: Option<String>,
+    /// The name of the machine on which the result was recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    machine: Option<String>,
+    result: QuestionResult,
 }
 
 /// Save `results` to a file in a results directory, appending the results if previous
-/// results have been recorded.
+/// results have been recorded. The results are appended to the results log if the quiz
+/// has one, and otherwise added to the JSON results file.
+///
+/// The results file is locked for the whole read-modify-write, so that concurrent
+/// sessions of the same quiz do not overwrite each other's results, and the new
+/// contents are written to a temporary file that is then renamed over the old one, so
+/// that a crash never leaves a half-written file behind. The previous version of the
+/// file is kept as a backup.
 pub fn save_results(fullname: &Path, results: &QuizResult) -> Result<()> {
     let results_dir = get_results_dir_path(fullname)?;
     if !results_dir.as_path().exists() {
-        fs::create_dir(&results_dir).map_err(QuizError::Io)?;
+        fs::create_dir_all(&results_dir).map_err(QuizError::Io)?;
     }
 
-    // Load old data, if it exists.
     let results_path = get_results_path(fullname)?;
+    let _lock = lock_file(&results_path)?;
+
+    let log_path = get_results_log_path(fullname)?;
+    if log_path.exists() {
+        let machine = machine_name();
+        let mut data = String::new();
+        for result in results.per_question.iter() {
+            let entry = LogEntry {
+                id: result.id.clone(),
+                session: None,
+                machine: machine.clone(),
+                result: result.clone(),
+            };
+            data.push_str(&serde_json::to_string(&entry).map_err(QuizError::Json)?);
+            data.push('\n');
+        }
+        append_to_file(&log_path, &data)?;
+    } else {
+        save_results_to_json(&results_path, results)?;
+    }
+
+    if let Some(session) = results.session.as_ref() {
+        let record = SessionRecord {
+            session: session.clone(),
+            time_finished: results.time_finished,
+            total: results.total,
+            score: results.score,
+        };
+        let mut data = serde_json::to_string(&record).map_err(QuizError::Json)?;
+        data.push('\n');
+        append_to_file(&get_sessions_path(fullname)?, &data)?;
+    }
+    Ok(())
+}
+
+fn save_results_to_json(results_path: &Path, results: &QuizResult) -> Result<()> {
+    // Load old data, if it exists.
     let data = fs::read_to_string(&results_path);
     let mut hash: BTreeMap<String, Vec<QuestionResult>> = match data {
-        Ok(ref data) => serde_json::from_str(&data).map_err(QuizError::Json)?,
+        Ok(ref data) => parse_results(&results_path, data)?,
         Err(_) => BTreeMap::new(),
     };
 
@@ -61,11 +155,469 @@ pub fn save_results(fullname: &Path, results: &QuizResult) -> Result<()> {
     }
 
     let serialized_results = serde_json::to_string_pretty(&hash).map_err(QuizError::Json)?;
-    fs::write(&results_path, serialized_results)
-        .or(Err(QuizError::CannotWriteToFile(results_path.clone())))?;
+    if results_path.exists() {
+        fs::copy(&results_path, get_backup_path(&results_path)).or(Err(
+            QuizError::CannotWriteToFile(results_path.to_path_buf()),
+        ))?;
+    }
+    write_atomically(&results_path, &serialized_results)
+}
+
+/// The record of a session of a quiz, as stored in the quiz's sessions log.
+///
+/// The sessions are not stored in the results file, which must remain a map from
+/// question IDs to lists of results so that older versions can still read it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionRecord {
+    #[serde(flatten)]
+    pub session: Session,
+    pub time_finished: chrono::DateTime<chrono::Utc>,
+    /// The number of questions answered, not counting practice attempts.
+    pub total: usize,
+    /// Score out of 1,000 possible points.
+    pub score: u64,
+}
+
+/// Load the records of the sessions of a quiz, in the order they were started.
+pub fn load_sessions(fullname: &Path) -> Result<Vec<SessionRecord>> {
+    let path = get_sessions_path(fullname)?;
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(_) => {
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut sessions: Vec<SessionRecord> = parse_lines(&path, &data)?;
+    sessions.sort_by_key(|s| s.session.time_started);
+    // Copies of the log that were merged by git may record the same session twice.
+    sessions.dedup_by(|a, b| a.session.id == b.session.id);
+    Ok(sessions)
+}
+
+/// Move all the results of a quiz into a file of the given format, and delete the
+/// results file of the other format. Return the number of results moved.
+///
+/// The sessions log is the same in either format, but it is rewritten as well, in order
+/// and without duplicates.
+pub fn convert_results(fullname: &Path, format: ResultsFormat) -> Result<usize> {
+    let results_path = get_results_path(fullname)?;
+    let log_path = get_results_log_path(fullname)?;
+    let _lock = lock_file(&results_path)?;
+
+    let results = load_results(fullname)?;
+    let count = results.values().map(|list| list.len()).sum();
+    let results_dir = get_results_dir_path(fullname)?;
+    if !results_dir.as_path().exists() {
+        fs::create_dir_all(&results_dir).map_err(QuizError::Io)?;
+    }
+
+    match format {
+        ResultsFormat::Json => {
+            let hash: BTreeMap<String, Vec<QuestionResult>> = results.into_iter().collect();
+            let serialized = serde_json::to_string_pretty(&hash).map_err(QuizError::Json)?;
+            write_atomically(&results_path, &serialized)?;
+            if log_path.exists() {
+                fs::remove_file(&log_path).map_err(QuizError::Io)?;
+            }
+        }
+        ResultsFormat::Jsonl => {
+            // The results are logged in the order they were recorded, as they would have
+            // been if the quiz had always had a results log.
+            let mut all: Vec<QuestionResult> = results.into_iter().flat_map(|(_, l)| l).collect();
+            all.sort_by(|a, b| a.time_asked.cmp(&b.time_asked).then(a.id.cmp(&b.id)));
+            let mut data = String::new();
+            for result in all.into_iter() {
+                let entry = LogEntry {
+                    id: result.id.clone(),
+                    session: None,
+                    machine: None,
+                    result,
+                };
+                data.push_str(&serde_json::to_string(&entry).map_err(QuizError::Json)?);
+                data.push('\n');
+            }
+            write_atomically(&log_path, &data)?;
+            if results_path.exists() {
+                fs::remove_file(&results_path).map_err(QuizError::Io)?;
+            }
+        }
+    }
+
+    let sessions = load_sessions(fullname)?;
+    if sessions.len() > 0 {
+        write_atomically(&get_sessions_path(fullname)?, &serialize_lines(&sessions)?)?;
+    }
+    Ok(count)
+}
+
+/// A summary of the results merged by `merge_results`.
+pub struct MergeSummary {
+    /// The number of results that were added.
+    pub added: usize,
+    /// The number of results that were skipped because they were already recorded.
+    pub duplicates: usize,
+    /// The number of results read for each question ID.
+    pub counts: BTreeMap<String, usize>,
+    /// The number of sessions that were added.
+    pub sessions: usize,
+}
+
+/// Merge the results files at `paths`, which may be in either format, into the results
+/// of a quiz, e.g. to combine the results recorded on different machines. A result is
+/// a duplicate of another if it is for the same question and was asked at the same
+/// time.
+///
+/// The sessions log next to each results file, e.g. `quiz_sessions.jsonl` next to
+/// `quiz_results.json`, is merged too if there is one. A session is a duplicate of
+/// another if it has the same ID.
+pub fn merge_results(fullname: &Path, paths: &[PathBuf]) -> Result<MergeSummary> {
+    let results_dir = get_results_dir_path(fullname)?;
+    if !results_dir.as_path().exists() {
+        fs::create_dir_all(&results_dir).map_err(QuizError::Io)?;
+    }
+
+    let results_path = get_results_path(fullname)?;
+    let _lock = lock_file(&results_path)?;
+    let mut results = load_results(fullname)?;
+
+    let mut summary = MergeSummary {
+        added: 0,
+        duplicates: 0,
+        counts: BTreeMap::new(),
+        sessions: 0,
+    };
+    let mut new_entries = Vec::new();
+    for path in paths.iter() {
+        for entry in read_results_file(path)? {
+            *summary.counts.entry(entry.id.clone()).or_insert(0) += 1;
+            let list = results.entry(entry.id.clone()).or_insert_with(Vec::new);
+            if list.iter().any(|r| r.time_asked == entry.result.time_asked) {
+                summary.duplicates += 1;
+                continue;
+            }
+
+            let mut result = entry.result.clone();
+            result.id = entry.id.clone();
+            list.push(result);
+            new_entries.push(entry);
+            summary.added += 1;
+        }
+    }
+
+    let log_path = get_results_log_path(fullname)?;
+    if log_path.exists() {
+        // Only append to the log, so that copies of it still merge cleanly.
+        new_entries.sort_by(|a, b| a.result.time_asked.cmp(&b.result.time_asked));
+        let mut data = String::new();
+        for entry in new_entries.iter() {
+            data.push_str(&serde_json::to_string(entry).map_err(QuizError::Json)?);
+            data.push('\n');
+        }
+        append_to_file(&log_path, &data)?;
+    } else {
+        let mut hash: BTreeMap<String, Vec<QuestionResult>> = results.into_iter().collect();
+        for list in hash.values_mut() {
+            list.sort_by_key(|r| r.time_asked);
+            list.dedup_by_key(|r| r.time_asked);
+        }
+
+        let serialized = serde_json::to_string_pretty(&hash).map_err(QuizError::Json)?;
+        if results_path.exists() {
+            fs::copy(&results_path, get_backup_path(&results_path))
+                .or(Err(QuizError::CannotWriteToFile(results_path.clone())))?;
+        }
+        write_atomically(&results_path, &serialized)?;
+    }
+
+    let mut known: HashSet<String> = load_sessions(fullname)?
+        .into_iter()
+        .map(|s| s.session.id)
+        .collect();
+    let mut new_sessions = Vec::new();
+    for path in paths.iter() {
+        if let Some(sessions_path) = get_sibling_sessions_path(path) {
+            if sessions_path.exists() {
+                for record in read_sessions_file(&sessions_path)? {
+                    if known.insert(record.session.id.clone()) {
+                        new_sessions.push(record);
+                    }
+                }
+            }
+        }
+    }
+    if new_sessions.len() > 0 {
+        new_sessions.sort_by_key(|s| s.session.time_started);
+        append_to_file(
+            &get_sessions_path(fullname)?,
+            &serialize_lines(&new_sessions)?,
+        )?;
+        summary.sessions = new_sessions.len();
+    }
+    Ok(summary)
+}
+
+/// Return the path of the sessions log that belongs with the results file at `path`,
+/// e.g. `quiz_sessions.jsonl` for `quiz_results.json`, or `None` if `path` is not named
+/// like a results file.
+fn get_sibling_sessions_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name
+        .strip_suffix("_results.json")
+        .or_else(|| name.strip_suffix("_results.jsonl"))?;
+    Some(path.with_file_name(format!("{}_sessions.jsonl", stem)))
+}
+
+/// Read the sessions log at `path`, which belongs to a different copy of a quiz.
+fn read_sessions_file(path: &Path) -> Result<Vec<SessionRecord>> {
+    let data = fs::read_to_string(path).map_err(|e| QuizError::BadResultsFile {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    parse_lines(path, &data).map_err(|e| match e {
+        QuizError::CorruptedResults { message, .. } => QuizError::BadResultsFile {
+            path: path.to_path_buf(),
+            message,
+        },
+        e => e,
+    })
+}
+
+/// Read the results file at `path`, which is taken to be a results log if its name
+/// ends in `.jsonl` and a JSON results file otherwise.
+fn read_results_file(path: &Path) -> Result<Vec<LogEntry>> {
+    let bad_file = |message: String| QuizError::BadResultsFile {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let data = fs::read_to_string(path).map_err(|e| bad_file(e.to_string()))?;
+    if path.extension().and_then(|e| e.to_str()) == Some("jsonl") {
+        return parse_results_log(path, &data).map_err(|e| match e {
+            QuizError::CorruptedResults { message, .. } => bad_file(message),
+            e => e,
+        });
+    }
+
+    let hash: BTreeMap<String, Vec<QuestionResult>> =
+        serde_json::from_str(&data).map_err(|e| bad_file(e.to_string()))?;
+    let mut entries = Vec::new();
+    for (id, list) in hash.into_iter() {
+        for result in list.into_iter() {
+            entries.push(LogEntry {
+                id: id.clone(),
+                session: None,
+                machine: None,
+                result,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Return the name of the machine that the program is running on.
+fn machine_name() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    let status =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if status != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8(buffer[..end].to_vec()).ok()
+}
+
+/// Parse the contents of the results log at `path`.
+fn parse_results_log(path: &Path, data: &str) -> Result<Vec<LogEntry>> {
+    let mut entries: Vec<LogEntry> = parse_lines(path, data)?;
+    for entry in entries.iter_mut() {
+        if entry.result.session.is_none() {
+            entry.result.session = entry.session.take();
+        }
+    }
+    Ok(entries)
+}
+
+/// Parse `data`, the contents of the file at `path`, as one JSON value per line.
+///
+/// A last line that cannot be parsed is skipped with a warning, since it is most likely
+/// an append that was cut short by a crash. Any other line that cannot be parsed is an
+/// error.
+fn parse_lines<T: serde::de::DeserializeOwned>(path: &Path, data: &str) -> Result<Vec<T>> {
+    let lines: Vec<(usize, &str)> = data
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim().len() > 0)
+        .collect();
+    let mut entries = Vec::new();
+    for (j, (i, line)) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(entry) => {
+                entries.push(entry);
+            }
+            Err(e) if j == lines.len() - 1 => {
+                let mut warned = WARNED.lock().unwrap();
+                if !warned.iter().any(|p| p == path) {
+                    eprintln!(
+                        "{}: ignoring truncated line {} of '{}' ({})",
+                        "Warning".yellow(),
+                        i + 1,
+                        path.to_string_lossy(),
+                        e
+                    );
+                    warned.push(path.to_path_buf());
+                }
+            }
+            Err(e) => {
+                return Err(QuizError::CorruptedResults {
+                    path: path.to_path_buf(),
+                    backup: None,
+                    message: format!("line {}: {}", i + 1, e),
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Serialize `values` as one JSON value per line.
+fn serialize_lines<T: Serialize>(values: &[T]) -> Result<String> {
+    let mut data = String::new();
+    for value in values.iter() {
+        data.push_str(&serde_json::to_string(value).map_err(QuizError::Json)?);
+        data.push('\n');
+    }
+    Ok(data)
+}
+
+/// Parse the contents of the results file at `path`.
+fn parse_results<T: serde::de::DeserializeOwned>(path: &Path, data: &str) -> Result<T> {
+    serde_json::from_str(data).map_err(|e| {
+        let backup = get_backup_path(path);
+        QuizError::CorruptedResults {
+            path: path.to_path_buf(),
+            backup: if backup.exists() { Some(backup) } else { None },
+            message: e.to_string(),
+        }
+    })
+}
+
+/// Replace the contents of the file at `path` with `data`, such that the file has either
+/// its old or its new contents even if the program crashes part way through.
+fn write_atomically(path: &Path, data: &str) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file =
+        File::create(&tmp_path).or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))?;
+    file.write_all(data.as_bytes())
+        .and_then(|_| file.sync_all())
+        .or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))?;
+    fs::rename(&tmp_path, path).or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))?;
     Ok(())
 }
 
+/// Append `data` to the file at `path`, creating the file if it does not exist.
+///
+/// If the file ends in a partial line that cannot be parsed, left behind by an append
+/// that was cut short, the partial line is removed first, so that it does not end up in
+/// the middle of the file where it would make the whole file unreadable.
+fn append_to_file(path: &Path, data: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))?;
+
+    let contents = fs::read_to_string(path).unwrap_or_else(|_| String::new());
+    let complete = contents.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let mut data = String::from(data);
+    if complete < contents.len() {
+        if serde_json::from_str::<serde_json::Value>(&contents[complete..]).is_ok() {
+            data.insert(0, '\n');
+        } else {
+            file.set_len(complete as u64)
+                .or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))?;
+        }
+    }
+
+    file.write_all(data.as_bytes())
+        .and_then(|_| file.sync_all())
+        .or(Err(QuizError::CannotWriteToFile(path.to_path_buf())))
+}
+
+/// An exclusive advisory lock on a file, released when it is dropped.
+struct FileLock {
+    _file: File,
+}
+
+/// Lock the file at `path` until the returned lock is dropped, waiting for any other
+/// process that holds the lock to release it. The lock is taken on a separate lock
+/// file, since `path` itself may be replaced while it is locked.
+fn lock_file(path: &Path) -> Result<FileLock> {
+    let mut lock_path = path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    let file = File::create(&lock_path).map_err(QuizError::Io)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(QuizError::Io(io::Error::last_os_error()));
+    }
+    Ok(FileLock { _file: file })
+}
+
+/// Load the FSRS parameters fitted for a quiz, or the default parameters if they have
+/// never been fitted.
+pub fn load_fsrs_parameters(fullname: &Path) -> Result<fsrs::Parameters> {
+    let path = get_fsrs_parameters_path(fullname)?;
+    match fs::read_to_string(&path) {
+        // A file with the wrong number of weights is rejected here, since the model
+        // indexes them directly.
+        Ok(data) => serde_json::from_str(&data).map_err(|e| QuizError::CorruptedParameters {
+            path: path.clone(),
+            message: e.to_string(),
+        }),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(fsrs::Parameters::default()),
+        Err(e) => Err(QuizError::Io(e)),
+    }
+}
+
+/// Save the FSRS parameters for a quiz in the results directory.
+pub fn save_fsrs_parameters(fullname: &Path, parameters: &fsrs::Parameters) -> Result<()> {
+    let results_dir = get_results_dir_path(fullname)?;
+    if !results_dir.as_path().exists() {
+        fs::create_dir_all(&results_dir).map_err(QuizError::Io)?;
+    }
+
+    let path = get_fsrs_parameters_path(fullname)?;
+    let serialized = serde_json::to_string_pretty(parameters).map_err(QuizError::Json)?;
+    write_atomically(&path, &serialized)
+}
+
+/// Return the paths of the quizzes in `directory`, in alphabetical order. A quiz is any
+/// file whose name does not begin with a dot and that either has no extension or has
+/// the extension `.quiz`.
+pub fn list_quizzes(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut quizzes = Vec::new();
+    for entry in fs::read_dir(directory).map_err(QuizError::Io)? {
+        let path = entry.map_err(QuizError::Io)?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with("."))
+            .unwrap_or(true);
+        let extension = path.extension().and_then(|e| e.to_str());
+        if !hidden && (extension.is_none() || extension == Some("quiz")) {
+            quizzes.push(path);
+        }
+    }
+    quizzes.sort();
+    Ok(quizzes)
+}
+
 fn get_results_dir_path(fullname: &Path) -> Result<PathBuf> {
     let mut builder = if let Some(parent) = fullname.parent() {
         parent.to_path_buf()
@@ -89,6 +641,95 @@ fn get_results_path(fullname: &Path) -> Result<PathBuf> {
     Ok(builder)
 }
 
+/// Return the path of the results log of a quiz, which is used instead of the JSON
+/// results file if it exists.
+fn get_results_log_path(fullname: &Path) -> Result<PathBuf> {
+    let mut path = get_results_path(fullname)?.into_os_string();
+    path.push("l");
+    Ok(PathBuf::from(path))
+}
+
+/// Return the path of the log of the sessions of a quiz.
+fn get_sessions_path(fullname: &Path) -> Result<PathBuf> {
+    let shortname = fullname
+        .file_name()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+    let shortname = shortname
+        .to_str()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+
+    let mut builder = get_results_dir_path(fullname)?;
+    builder.push(format!("{}_sessions.jsonl", shortname));
+    Ok(builder)
+}
+
+/// Return the path of the journal of the session of a quiz with the given ID.
+pub fn get_journal_path(fullname: &Path, session_id: &str) -> Result<PathBuf> {
+    let mut builder = get_results_dir_path(fullname)?;
+    builder.push(format!(
+        "{}{}.jsonl",
+        get_journal_prefix(fullname)?,
+        session_id
+    ));
+    Ok(builder)
+}
+
+/// Return the paths of the journals of every session of a quiz, in alphabetical order.
+pub fn list_journals(fullname: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = get_journal_prefix(fullname)?;
+    let entries = match fs::read_dir(get_results_dir_path(fullname)?) {
+        Ok(entries) => entries,
+        Err(_) => {
+            return Ok(Vec::new());
+        }
+    };
+
+    let mut journals = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(QuizError::Io)?.path();
+        let is_journal = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with(&prefix) && name.ends_with(".jsonl"))
+            .unwrap_or(false);
+        if is_journal {
+            journals.push(path);
+        }
+    }
+    journals.sort();
+    Ok(journals)
+}
+
+fn get_journal_prefix(fullname: &Path) -> Result<String> {
+    let shortname = fullname
+        .file_name()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+    let shortname = shortname
+        .to_str()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+    Ok(format!("{}_journal_", shortname))
+}
+
+/// Return the path of the backup of the results file at `path`.
+fn get_backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+fn get_fsrs_parameters_path(fullname: &Path) -> Result<PathBuf> {
+    let shortname = fullname
+        .file_name()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+    let shortname = shortname
+        .to_str()
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))?;
+
+    let mut builder = get_results_dir_path(fullname)?;
+    builder.push(format!("{}_fsrs.json", shortname));
+    Ok(builder)
+}
+
 fn parse(path: &Path, old_results: &StoredResults) -> Result<Quiz> {
     let file = File::open(path).map_err(QuizError::Io)?;
     let mut reader = QuizReader::new(BufReader::new(file));
@@ -130,9 +771,21 @@ fn parse(path: &Path, old_results: &StoredResults) -> Result<Quiz> {
         }
     }
 
+    check_order_constraints(&questions)?;
+
     Ok(Quiz {
         instructions: quiz_settings.instructions,
         questions,
+        scheduler: quiz_settings.scheduler,
+        fsrs_parameters: fsrs::Parameters::default(),
+        leech_threshold: quiz_settings
+            .leech_threshold
+            .unwrap_or(repetition::DEFAULT_LEECH_THRESHOLD),
+        suspend_leeches: quiz_settings.suspend_leeches,
+        calendar: repetition::Calendar {
+            rollover_hour: quiz_settings.day_rollover.unwrap_or(0),
+            offset: quiz_settings.timezone,
+        },
     })
 }
 
@@ -145,33 +798,47 @@ fn entry_to_question(
     let tags = entry
         .attributes
         .get("tags")
-        .map(|v| split(v, ","))
+        .map(|v| split(v, ','))
         .unwrap_or(Vec::new());
 
+    let after = entry
+        .attributes
+        .get("after")
+        .map(|v| split(v, ','))
+        .unwrap_or(Vec::new());
+
+    // Practice attempts from `--relearn` sessions are kept in the results file but
+    // ignored when scheduling.
     let prior_results = old_results
         .get(&entry.id)
-        .map(|v| v.clone())
+        .map(|v| {
+            v.iter()
+                .filter(|r| r.practice != Some(true))
+                .cloned()
+                .collect()
+        })
         .unwrap_or(Vec::new());
 
     let common = QuestionCommon {
         id: entry.id.clone(),
         prior_results,
         tags,
+        after,
         location: entry.location.clone(),
     };
 
     // TODO: Handle multiple question texts.
     let entry = entry.clone();
-    let text = entry.text.clone();
+    let text = unescape(&entry.text);
     if entry.following.len() == 1 {
-        check_fields(&entry.attributes, &["choices", "tags"], lineno)?;
+        check_fields(&entry.attributes, &["after", "choices", "tags"], lineno)?;
 
-        let answer = split(&entry.following[0], "/");
+        let answer = split(&entry.following[0], '/');
         if let Some(choices) = entry.attributes.get("choices") {
             return Ok(Box::new(MultipleChoiceQuestion {
                 text,
                 answer,
-                choices: split(&choices, "/"),
+                choices: split(&choices, '/'),
                 common,
             }));
         } else {
@@ -182,16 +849,16 @@ fn entry_to_question(
             }));
         }
     } else if entry.following.len() == 0 {
-        if let Some(equal) = entry.text.find("=") {
-            check_fields(&entry.attributes, &["tags"], lineno)?;
+        if let Some(equal) = find_unescaped(&entry.text, '=') {
+            check_fields(&entry.attributes, &["after", "tags"], lineno)?;
 
             let frnt = entry.text[..equal].trim().to_string();
             let (front, front_context) = get_context(&frnt, lineno)?;
             let bck = &entry.text[equal + 1..];
             let (back, back_context) = get_context(&bck, lineno)?;
             return Ok(Box::new(FlashcardQuestion {
-                front: split(&front, "/"),
-                back: split(&back, "/"),
+                front: split(&front, '/'),
+                back: split(&back, '/'),
                 front_context,
                 back_context,
                 common,
@@ -247,7 +914,11 @@ fn entry_to_question(
             });
         }
     } else {
-        check_fields(&entry.attributes, &["nocredit", "ordered", "tags"], lineno)?;
+        check_fields(
+            &entry.attributes,
+            &["after", "nocredit", "ordered", "tags"],
+            lineno,
+        )?;
 
         let ordered = if let Some(_ordered) = entry.attributes.get("ordered") {
             if _ordered != "true" && _ordered != "false" {
@@ -264,12 +935,12 @@ fn entry_to_question(
         };
 
         let no_credit = if let Some(_no_credit) = entry.attributes.get("nocredit") {
-            split(&_no_credit, "/")
+            split(&_no_credit, '/')
         } else {
             Vec::new()
         };
 
-        let answer_list = entry.following.iter().map(|l| split(&l, "/")).collect();
+        let answer_list = entry.following.iter().map(|l| split(&l, '/')).collect();
         if ordered {
             return Ok(Box::new(OrderedListQuestion {
                 text,
@@ -288,20 +959,178 @@ fn entry_to_question(
     };
 }
 
+/// Check that every ID named in an `after` field refers to a question in the quiz, and
+/// that the `after` fields do not form a cycle.
+fn check_order_constraints(questions: &Vec<Box<dyn Question>>) -> Result<()> {
+    let mut positions = HashMap::new();
+    for (i, q) in questions.iter().enumerate() {
+        positions.insert(q.get_common().id.as_str(), i);
+    }
+
+    for q in questions.iter() {
+        for id in q.get_common().after.iter() {
+            if !positions.contains_key(id.as_str()) {
+                return Err(QuizError::Parse {
+                    line: q.get_common().location.line,
+                    whole_entry: true,
+                    message: format!("after field refers to nonexistent question '{}'", id),
+                });
+            }
+        }
+    }
+
+    // Depth-first search over the graph of `after` fields. A question that is reached
+    // again while it is still on the stack is part of a cycle.
+    let mut visited = vec![false; questions.len()];
+    let mut on_stack = vec![false; questions.len()];
+    for start in 0..questions.len() {
+        if visited[start] {
+            continue;
+        }
+
+        let mut stack = vec![(start, 0)];
+        visited[start] = true;
+        on_stack[start] = true;
+        while let Some((current, next_edge)) = stack.pop() {
+            let after = &questions[current].get_common().after;
+            if next_edge < after.len() {
+                stack.push((current, next_edge + 1));
+                let target = positions[after[next_edge].as_str()];
+                if on_stack[target] {
+                    return Err(QuizError::Parse {
+                        line: questions[current].get_common().location.line,
+                        whole_entry: true,
+                        message: String::from("cycle in after fields"),
+                    });
+                } else if !visited[target] {
+                    visited[target] = true;
+                    on_stack[target] = true;
+                    stack.push((target, 0));
+                }
+            } else {
+                on_stack[current] = false;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse an offset from UTC like `+05:30` or `-08:00`, or `UTC` for no offset.
+fn parse_utc_offset(val: &str) -> Option<chrono::FixedOffset> {
+    if val == "UTC" {
+        return chrono::FixedOffset::east_opt(0);
+    }
+
+    let sign = match val.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let mut parts = val[1..].splitn(2, ':');
+    let hours = parts.next()?;
+    let minutes = parts.next()?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours = u32::from_str_radix(hours, 10).ok()?;
+    let minutes = u32::from_str_radix(minutes, 10).ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    chrono::FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60) as i32)
+}
+
 #[derive(Debug)]
 struct GlobalSettings {
     instructions: Option<String>,
+    scheduler: Option<SchedulerKind>,
+    leech_threshold: Option<usize>,
+    suspend_leeches: bool,
+    day_rollover: Option<u32>,
+    timezone: Option<chrono::FixedOffset>,
 }
 
 /// Read the initial settings from the file.
 fn read_settings(reader: &mut QuizReader) -> Result<GlobalSettings> {
-    let mut settings = GlobalSettings { instructions: None };
+    let mut settings = GlobalSettings {
+        instructions: None,
+        scheduler: None,
+        leech_threshold: None,
+        suspend_leeches: false,
+        day_rollover: None,
+        timezone: None,
+    };
     let mut first_line = true;
     loop {
         match reader.read_line()? {
             Some(FileLine::Pair(key, val)) => {
                 if key == "instructions" {
                     settings.instructions.replace(val);
+                } else if key == "scheduler" {
+                    if let Some(kind) = SchedulerKind::from_name(&val) {
+                        settings.scheduler.replace(kind);
+                    } else {
+                        return Err(QuizError::Parse {
+                            line: reader.line,
+                            whole_entry: false,
+                            message: format!("unknown scheduler '{}'", val),
+                        });
+                    }
+                } else if key == "leech-threshold" {
+                    match usize::from_str_radix(&val, 10) {
+                        Ok(n) if n > 0 => {
+                            settings.leech_threshold.replace(n);
+                        }
+                        _ => {
+                            return Err(QuizError::Parse {
+                                line: reader.line,
+                                whole_entry: false,
+                                message: String::from(
+                                    "leech-threshold field must be a positive integer",
+                                ),
+                            });
+                        }
+                    }
+                } else if key == "suspend-leeches" {
+                    if val != "true" && val != "false" {
+                        return Err(QuizError::Parse {
+                            line: reader.line,
+                            whole_entry: false,
+                            message: String::from(
+                                "suspend-leeches field must be either 'true' or 'false'",
+                            ),
+                        });
+                    }
+                    settings.suspend_leeches = val == "true";
+                } else if key == "day-rollover" {
+                    match u32::from_str_radix(&val, 10) {
+                        Ok(hour) if hour < 24 => {
+                            settings.day_rollover.replace(hour);
+                        }
+                        _ => {
+                            return Err(QuizError::Parse {
+                                line: reader.line,
+                                whole_entry: false,
+                                message: String::from(
+                                    "day-rollover field must be an hour from 0 to 23",
+                                ),
+                            });
+                        }
+                    }
+                } else if key == "timezone" {
+                    if val != "local" {
+                        if let Some(offset) = parse_utc_offset(&val) {
+                            settings.timezone.replace(offset);
+                        } else {
+                            return Err(QuizError::Parse {
+                                line: reader.line,
+                                whole_entry: false,
+                                message: String::from(
+                                    "timezone field must be 'local', 'UTC' or an offset like '+05:30'",
+                                ),
+                            });
+                        }
+                    }
                 } else {
                     return Err(QuizError::Parse {
                         line: reader.line,
@@ -397,7 +1226,7 @@ fn read_entry(path: &Path, reader: &mut QuizReader) -> Result<Option<FileEntry>>
                             break;
                         }
                         Some(FileLine::Pair(key, value)) => {
-                            entry.choices.insert(key, split(&value, "/"));
+                            entry.choices.insert(key, split(&value, '/'));
                         }
                         Some(_) => {
                             return Err(QuizError::Parse {
@@ -427,8 +1256,76 @@ fn read_entry(path: &Path, reader: &mut QuizReader) -> Result<Option<FileEntry>>
     }
 }
 
-fn split(s: &str, splitter: &str) -> Vec<String> {
-    s.split(splitter).map(|w| w.trim().to_string()).collect()
+/// Characters that lose their special meaning when preceded by a backslash, e.g.
+/// `AC\/DC` is a single answer rather than two variants. A backslash before any other
+/// character, or at the end of a line, is kept as is, so that older quizzes containing
+/// backslashes still parse.
+const ESCAPABLE: [char; 6] = ['\\', '/', '=', '[', ']', ','];
+
+/// Split `s` on every occurrence of `splitter` that is not escaped with a backslash,
+/// and trim and unescape each piece.
+pub fn split(s: &str, splitter: char) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = s;
+    while let Some(index) = find_unescaped(rest, splitter) {
+        pieces.push(unescape(rest[..index].trim()));
+        rest = &rest[index + splitter.len_utf8()..];
+    }
+    pieces.push(unescape(rest.trim()));
+    pieces
+}
+
+/// Return the byte index of the first occurrence of `c` in `s` that is not escaped
+/// with a backslash.
+fn find_unescaped(s: &str, c: char) -> Option<usize> {
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, next)) = chars.next() {
+        if next == '\\' {
+            if let Some((_, escaped)) = chars.peek() {
+                if ESCAPABLE.contains(escaped) {
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+
+        if next == c {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Escape every character in `s` that has a special meaning in quiz files, so that
+/// `split` and `unescape` return `s` unchanged.
+pub fn escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        if ESCAPABLE.contains(&c) {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Remove the backslashes from all escape sequences in `s`.
+fn unescape(s: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(next) = chars.next() {
+        if next == '\\' {
+            if let Some(escaped) = chars.peek() {
+                if ESCAPABLE.contains(escaped) {
+                    ret.push(*escaped);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        ret.push(next);
+    }
+    ret
 }
 
 enum FileLine {
@@ -520,8 +1417,8 @@ impl QuizReader {
                     message: String::from("expected colon"),
                 })
             }
-        } else if trimmed.starts_with("[") && trimmed.find("]").is_some() {
-            let brace = trimmed.find("]").unwrap();
+        } else if trimmed.starts_with("[") && find_unescaped(trimmed, ']').is_some() {
+            let brace = find_unescaped(trimmed, ']').unwrap();
             let id = &trimmed[1..brace];
             let rest = &trimmed[brace + 1..];
             Ok(Some(FileLine::First(
@@ -545,12 +1442,12 @@ impl QuizReader {
     }
 }
 
-fn get_context(line: &str, lineno: usize) -> Result<(String, Option<String>)> {
-    if let Some(open) = line.find('[') {
-        if let Some(_close) = line[open..].find(']') {
+pub fn get_context(line: &str, lineno: usize) -> Result<(String, Option<String>)> {
+    if let Some(open) = find_unescaped(line, '[') {
+        if let Some(_close) = find_unescaped(&line[open..], ']') {
             let close = _close + open;
             let new_line = String::from(line[..open].trim());
-            let context = String::from(line[open + 1..close].trim());
+            let context = unescape(line[open + 1..close].trim());
             Ok((new_line, Some(context)))
         } else {
             Err(QuizError::Parse {
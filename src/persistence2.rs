@@ -1,34 +1,467 @@
+/**
+ * Functions for reading and writing quizzes stored in SQLite databases.
+ *
+ * Each row of the `answers` table holds one answer to a question. Equivalent variants
+ * of the answer are separated by forward slashes, exactly as in the text format, and
+ * a literal slash is escaped with a backslash. Flashcards have two answers, the front
+ * and the back, and each may be followed by a bracketed context as in the text format.
+ *
+ * Times in the `results` table are stored as RFC 3339 strings with nanosecond precision
+ * in UTC, so that sorting them as text sorts them chronologically.
+ *
+ * Author:  Ian Fisher (iafisher@fastmail.com)
+ * Version: October 2019
+ */
 use std::collections::HashMap;
 use std::path::Path;
 
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
 
 use super::common::{QuizError, Result};
-use super::quiz2::{Answer2, Question2, QuestionType, Quiz2};
+use super::persistence;
+use super::quiz2::{Answer2, Question2, QuestionResult2, QuestionType, Quiz2, QuizResult2};
 
+/// Load a `Quiz2` object from the database at `fullname`. The name of the quiz within
+/// the database is the file name of `fullname`, without its extension.
 pub fn load_quiz(fullname: &Path) -> Result<Quiz2> {
-    // let exists = fullname.exists();
-    // let connection = Connection::open(&path);
-    let exists = false;
-    let connection = Connection::open_in_memory().map_err(QuizError::Sql)?;
-    if !exists {
+    // Otherwise `open_database` would create an empty database at a mistyped path.
+    if !fullname.exists() {
+        return Err(QuizError::QuizNotFound(fullname.to_path_buf()));
+    }
+
+    let name = get_quiz_name(fullname)?;
+    let connection = open_database(fullname)?;
+
+    let mut stmt = connection
+        .prepare("SELECT id, instructions, version FROM quizzes WHERE name = ?")
+        .map_err(QuizError::Sql)?;
+    let mut rows = stmt.query(params![name]).map_err(QuizError::Sql)?;
+    let (quiz_id, instructions, version) = if let Some(row) = rows.next().map_err(QuizError::Sql)? {
+        (
+            row.get_unwrap::<usize, i64>(0),
+            row.get_unwrap::<usize, String>(1),
+            row.get_unwrap::<usize, String>(2),
+        )
+    } else {
+        return Err(QuizError::QuizNotFound(fullname.to_path_buf()));
+    };
+
+    let sql = "
+            SELECT
+              questions.id,
+              questions.text,
+              questions.type,
+              answers.text,
+              answers.correct,
+              answers.no_credit
+            FROM
+              questions
+            LEFT JOIN
+              answers
+            ON
+              answers.question = questions.id
+            WHERE
+              questions.quiz = ?
+            ORDER BY
+              questions.id, answers.rowid
+        ";
+
+    let mut stmt = connection.prepare(sql).map_err(QuizError::Sql)?;
+    let mut rows = stmt.query(params![quiz_id]).map_err(QuizError::Sql)?;
+
+    let mut questions: Vec<Question2> = Vec::new();
+    let mut positions = HashMap::new();
+    while let Some(row) = rows.next().map_err(QuizError::Sql)? {
+        let id = row.get_unwrap::<usize, i64>(0);
+        if !positions.contains_key(&id) {
+            let question_text = row.get_unwrap::<usize, String>(1);
+            let question_type_string = row.get_unwrap::<usize, String>(2);
+            positions.insert(id, questions.len());
+            questions.push(Question2 {
+                id: id,
+                text: question_text,
+                question_type: string_to_question_type(&question_type_string)?,
+                answers: Vec::new(),
+                tags: Vec::new(),
+                prior_results: Vec::new(),
+            });
+        }
+
+        // Questions without any answers still produce a row from the left join.
+        let answer_text = row.get_unwrap::<usize, Option<String>>(3);
+        if let Some(answer_text) = answer_text {
+            let answer_correct = row.get_unwrap::<usize, i64>(4);
+            let answer_no_credit = row.get_unwrap::<usize, i64>(5);
+            let question = &mut questions[positions[&id]];
+            let (answer_text, context) = if question.question_type == QuestionType::Flashcard {
+                persistence::get_context(&answer_text, 0)?
+            } else {
+                (answer_text, None)
+            };
+            question.answers.push(Answer2 {
+                variants: persistence::split(&answer_text, '/'),
+                correct: answer_correct != 0,
+                no_credit: answer_no_credit != 0,
+                context,
+            });
+        }
+    }
+
+    let sql = "
+            SELECT
+              tags.question,
+              tags.name
+            FROM
+              tags
+            JOIN
+              questions
+            ON
+              tags.question = questions.id
+            WHERE
+              questions.quiz = ?
+        ";
+
+    let mut stmt = connection.prepare(sql).map_err(QuizError::Sql)?;
+    let mut rows = stmt.query(params![quiz_id]).map_err(QuizError::Sql)?;
+    while let Some(row) = rows.next().map_err(QuizError::Sql)? {
+        let id = row.get_unwrap::<usize, i64>(0);
+        if let Some(position) = positions.get(&id) {
+            questions[*position]
+                .tags
+                .push(row.get_unwrap::<usize, String>(1));
+        }
+    }
+
+    let mut old_results = load_results(&connection, quiz_id)?;
+    for question in questions.iter_mut() {
+        if let Some(prior_results) = old_results.remove(&question.id) {
+            question.prior_results = prior_results;
+        }
+    }
+
+    Ok(Quiz2 {
+        instructions: if instructions.len() > 0 {
+            Some(instructions)
+        } else {
+            None
+        },
+        version,
+        questions,
+    })
+}
+
+/// Save `quiz` to the database under `name`. Questions with a nonzero `id` are saved
+/// with that ID; the `id` field of every other question is set to the ID of its new row.
+pub fn save_quiz(connection: &Connection, name: &str, quiz: &mut Quiz2) -> Result<()> {
+    let instructions = quiz.instructions.clone().unwrap_or(String::new());
+    connection
+        .execute(
+            "INSERT INTO quizzes(name, instructions, version) VALUES (?, ?, ?)",
+            params![name, instructions, quiz.version],
+        )
+        .map_err(QuizError::Sql)?;
+    let quiz_id = connection.last_insert_rowid();
+
+    for question in quiz.questions.iter_mut() {
+        // SQLite assigns a fresh ID when the primary key is NULL.
+        let id = if question.id != 0 {
+            Some(question.id)
+        } else {
+            None
+        };
         connection
             .execute(
-                "
+                "INSERT INTO questions(id, quiz, text, type) VALUES (?, ?, ?, ?)",
+                params![
+                    id,
+                    quiz_id,
+                    question.text,
+                    question_type_to_string(&question.question_type)
+                ],
+            )
+            .map_err(QuizError::Sql)?;
+        question.id = connection.last_insert_rowid();
+
+        for answer in question.answers.iter() {
+            let mut text = answer
+                .variants
+                .iter()
+                .map(|v| persistence::escape(v))
+                .collect::<Vec<String>>()
+                .join(" / ");
+            if let Some(context) = &answer.context {
+                text.push_str(&format!(" [{}]", persistence::escape(context)));
+            }
+
+            connection
+                .execute(
+                    "INSERT INTO answers(question, text, correct, no_credit) VALUES (?, ?, ?, ?)",
+                    params![question.id, text, answer.correct, answer.no_credit],
+                )
+                .map_err(QuizError::Sql)?;
+        }
+
+        for tag in question.tags.iter() {
+            connection
+                .execute(
+                    "INSERT INTO tags(question, name) VALUES (?, ?)",
+                    params![question.id, tag],
+                )
+                .map_err(QuizError::Sql)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load the results of every question in the quiz with ID `quiz_id`, as a map from
+/// question IDs to results in chronological order.
+pub fn load_results(
+    connection: &Connection,
+    quiz_id: i64,
+) -> Result<HashMap<i64, Vec<QuestionResult2>>> {
+    let sql = "
+            SELECT
+              results.question,
+              results.text,
+              results.time_asked,
+              results.response,
+              results.response_list,
+              results.score,
+              results.session
+            FROM
+              results
+            JOIN
+              questions
+            ON
+              results.question = questions.id
+            WHERE
+              questions.quiz = ?
+            ORDER BY
+              results.time_asked
+        ";
+
+    let mut stmt = connection.prepare(sql).map_err(QuizError::Sql)?;
+    let mut rows = stmt.query(params![quiz_id]).map_err(QuizError::Sql)?;
+
+    let mut results = HashMap::new();
+    while let Some(row) = rows.next().map_err(QuizError::Sql)? {
+        let id = row.get_unwrap::<usize, i64>(0);
+        let time_asked = row.get_unwrap::<usize, String>(2);
+        let time_asked = chrono::DateTime::parse_from_rfc3339(&time_asked)
+            .map_err(|e| {
+                QuizError::Sql(rusqlite::Error::FromSqlConversionFailure(
+                    2,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                ))
+            })?
+            .with_timezone(&chrono::Utc);
+        let response_list = row.get_unwrap::<usize, Option<String>>(4);
+        let response_list = if let Some(response_list) = response_list {
+            Some(serde_json::from_str(&response_list).map_err(QuizError::Json)?)
+        } else {
+            None
+        };
+
+        results
+            .entry(id)
+            .or_insert(Vec::new())
+            .push(QuestionResult2 {
+                id,
+                text: row.get_unwrap::<usize, Option<String>>(1),
+                time_asked,
+                response: row.get_unwrap::<usize, Option<String>>(3),
+                response_list,
+                score: row.get_unwrap::<usize, i64>(5) as u64,
+                session: row.get_unwrap::<usize, Option<i64>>(6),
+            });
+    }
+    Ok(results)
+}
+
+/// Save the results of a session to the database at `fullname`. All the results are
+/// recorded under a new session ID.
+pub fn save_results(fullname: &Path, results: &QuizResult2) -> Result<()> {
+    let mut connection = open_database(fullname)?;
+    let transaction = connection.transaction().map_err(QuizError::Sql)?;
+
+    let session: i64 = transaction
+        .query_row(
+            "SELECT COALESCE(MAX(session), 0) + 1 FROM results",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(QuizError::Sql)?;
+
+    for result in results.per_question.iter() {
+        let mut result = result.clone();
+        result.session = Some(session);
+        save_result(&transaction, &result)?;
+    }
+    transaction.commit().map_err(QuizError::Sql)
+}
+
+/// Save a single result to the `results` table.
+pub fn save_result(connection: &Connection, result: &QuestionResult2) -> Result<()> {
+    let response_list = if let Some(response_list) = &result.response_list {
+        Some(serde_json::to_string(response_list).map_err(QuizError::Json)?)
+    } else {
+        None
+    };
+
+    connection
+        .execute(
+            "
+            INSERT INTO
+              results(question, text, time_asked, response, response_list, score, session)
+            VALUES
+              (?, ?, ?, ?, ?, ?, ?)
+            ",
+            params![
+                result.id,
+                result.text,
+                result
+                    .time_asked
+                    .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+                result.response,
+                response_list,
+                result.score as i64,
+                result.session,
+            ],
+        )
+        .map_err(QuizError::Sql)?;
+    Ok(())
+}
+
+/// Write the text-format quiz at `name` and its results to a new database at `output`,
+/// and return the number of questions and results migrated.
+///
+/// Question IDs are kept as the IDs of the rows in the `questions` table, so they must
+/// be positive integers. The database has no place for `after` constraints, so quizzes
+/// that use them are rejected rather than migrated without them.
+pub fn migrate_quiz(name: &Path, output: &Path) -> Result<(usize, usize)> {
+    let old_quiz = persistence::load_quiz(name)?;
+    let mut questions = Vec::new();
+    for old in old_quiz.questions.iter() {
+        let common = old.get_common();
+        if common.after.len() > 0 {
+            return Err(QuizError::CannotMigrate(format!(
+                "question {} has an `after` field",
+                common.id
+            )));
+        }
+
+        let mut question = old.to_question2();
+        question.id = match common.id.parse::<i64>() {
+            Ok(id) if id > 0 => id,
+            _ => {
+                return Err(QuizError::CannotMigrate(format!(
+                    "question ID {} is not a positive integer",
+                    common.id
+                )));
+            }
+        };
+        questions.push(question);
+    }
+    let mut quiz = Quiz2 {
+        instructions: old_quiz.instructions.clone(),
+        questions,
+        version: String::from("1.0"),
+    };
+
+    let mut connection = open_database(output)?;
+    let transaction = connection.transaction().map_err(QuizError::Sql)?;
+    save_quiz(&transaction, &get_quiz_name(output)?, &mut quiz)?;
+
+    let mut nresults = 0;
+    for (old, new) in old_quiz.questions.iter().zip(quiz.questions.iter()) {
+        for result in old.get_common().prior_results.iter() {
+            let result = QuestionResult2 {
+                id: new.id,
+                text: result.text.clone(),
+                time_asked: result.time_asked,
+                response: result.response.clone(),
+                response_list: result.response_list.clone(),
+                score: result.score,
+                session: None,
+            };
+            save_result(&transaction, &result)?;
+            nresults += 1;
+        }
+    }
+    transaction.commit().map_err(QuizError::Sql)?;
+
+    // Reload the database from scratch to check that nothing was lost.
+    let migrated = load_quiz(output)?;
+    if migrated.questions.len() != quiz.questions.len() {
+        return Err(QuizError::MigrationMismatch(format!(
+            "expected {} questions, got {}",
+            quiz.questions.len(),
+            migrated.questions.len()
+        )));
+    }
+
+    for expected in quiz.questions.iter() {
+        let new = migrated
+            .questions
+            .iter()
+            .find(|q| q.id == expected.id)
+            .ok_or(QuizError::MigrationMismatch(format!(
+                "question {} is missing",
+                expected.id
+            )))?;
+        if new.question_type != expected.question_type {
+            return Err(QuizError::MigrationMismatch(format!(
+                "wrong type for question {}",
+                expected.id
+            )));
+        } else if new.answers != expected.answers {
+            return Err(QuizError::MigrationMismatch(format!(
+                "wrong answers for question {}",
+                expected.id
+            )));
+        }
+    }
+
+    Ok((quiz.questions.len(), nresults))
+}
+
+/// Open the database at `fullname`, creating the tables if the file did not already
+/// exist.
+pub fn open_database(fullname: &Path) -> Result<Connection> {
+    let exists = fullname.exists();
+    let connection = Connection::open(fullname).map_err(QuizError::Sql)?;
+    if !exists {
+        create_schema(&connection)?;
+    } else {
+        connection
+            .execute_batch(RESULTS_TABLE)
+            .map_err(QuizError::Sql)?;
+    }
+    Ok(connection)
+}
+
+/// Return the name under which the quiz at `fullname` is stored in the `quizzes` table.
+pub fn get_quiz_name(fullname: &Path) -> Result<String> {
+    fullname
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(String::from)
+        .ok_or(QuizError::QuizNotFound(fullname.to_path_buf()))
+}
+
+fn create_schema(connection: &Connection) -> Result<()> {
+    connection
+        .execute_batch(
+            "
             CREATE TABLE quizzes(
               id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
               name TEXT UNIQUE NOT NULL CHECK(name != ''),
               instructions TEXT NOT NULL,
               version TEXT NOT NULL CHECK(version != ''),
               created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            ",
-                [],
-            )
-            .map_err(QuizError::Sql)?;
-        connection
-            .execute(
-                "
+            );
+
             CREATE TABLE questions(
               id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
               quiz INTEGER NOT NULL REFERENCES quizzes,
@@ -41,136 +474,184 @@ pub fn load_quiz(fullname: &Path) -> Result<Quiz2> {
                 type = 'flashcard'
               ),
               created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            ",
-                [],
-            )
-            .map_err(QuizError::Sql)?;
-        connection
-            .execute(
-                "
+            );
+
             CREATE TABLE answers(
               question INTEGER NOT NULL REFERENCES questions,
               text TEXT NOT NULL CHECK(text != ''),
               correct BOOLEAN NOT NULL DEFAULT 1,
               no_credit BOOLEAN NOT NULL DEFAULT 0,
               created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            ",
-                [],
-            )
-            .map_err(QuizError::Sql)?;
-        connection
-            .execute(
-                "
+            );
+
             CREATE TABLE tags(
               question INTEGER NOT NULL REFERENCES questions,
               name TEXT NOT NULL CHECK(name != ''),
               created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            ",
-                [],
-            )
-            .map_err(QuizError::Sql)?;
-    }
-
-    connection
-        .execute("INSERT INTO quizzes(name) VALUES ('main')", [])
-        .map_err(QuizError::Sql)?;
+            );
 
-    connection
-        .execute(
-            "
-        INSERT INTO
-          questions(quiz, type, text)
-        VALUES
-          (1, 'short answer', 'What is the capital of Tanzania?')
-        ",
-            [],
+            ",
         )
         .map_err(QuizError::Sql)?;
-
     connection
-        .execute(
-            "
-        INSERT INTO
-          answers(question, text)
-        VALUES
-          (1, 'Dodoma')
-        ",
-            [],
-        )
-        .map_err(QuizError::Sql)?;
+        .execute_batch(RESULTS_TABLE)
+        .map_err(QuizError::Sql)
+}
 
-    connection
-        .execute(
-            "
-        INSERT INTO
-          answers(question, text)
-        VALUES
-          (1, 'Dar es Salaam')
-        ",
-            [],
-        )
-        .map_err(QuizError::Sql)?;
+// Databases written by migrate.py are missing the `results` table, so it is created
+// separately if it does not exist.
+const RESULTS_TABLE: &'static str = "
+    CREATE TABLE IF NOT EXISTS results(
+      question INTEGER NOT NULL REFERENCES questions,
+      text TEXT,
+      time_asked TEXT NOT NULL,
+      response TEXT,
+      response_list TEXT,
+      score INTEGER NOT NULL,
+      session INTEGER,
+      created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+";
 
-    let sql = "
-            SELECT
-              questions.id,
-              questions.text,
-              questions.type,
-              answers.text,
-              answers.correct,
-              answers.no_credit
-            FROM
-              questions
-            LEFT JOIN
-              answers
-            ON
-              answers.question = questions.id
-        ";
+/// Convert a `QuestionType` into the `type` column of the `questions` table.
+pub fn question_type_to_string(question_type: &QuestionType) -> &'static str {
+    match question_type {
+        QuestionType::ShortAnswer => "short answer",
+        QuestionType::Ordered => "ordered",
+        QuestionType::Unordered => "unordered",
+        QuestionType::MultipleChoice => "multiple choice",
+        QuestionType::Flashcard => "flashcard",
+    }
+}
 
-    let mut stmt = connection.prepare(sql).map_err(QuizError::Sql)?;
-    let mut rows = stmt.query([]).map_err(QuizError::Sql)?;
+/// Convert the `type` column of the `questions` table into a `QuestionType`.
+pub fn string_to_question_type(s: &str) -> Result<QuestionType> {
+    match s {
+        "short answer" => Ok(QuestionType::ShortAnswer),
+        "ordered" => Ok(QuestionType::Ordered),
+        "unordered" => Ok(QuestionType::Unordered),
+        "multiple choice" => Ok(QuestionType::MultipleChoice),
+        "flashcard" => Ok(QuestionType::Flashcard),
+        _ => Err(QuizError::Sql(rusqlite::Error::InvalidColumnType(
+            2,
+            String::from("type"),
+            rusqlite::types::Type::Text,
+        ))),
+    }
+}
 
-    let mut questions_map = HashMap::new();
-    while let Some(row) = rows.next().map_err(QuizError::Sql)? {
-        let id = row.get_unwrap::<usize, i64>(0);
-        if !questions_map.contains_key(&id) {
-            let question_text = row.get_unwrap::<usize, String>(1);
-            let question_type_string = row.get_unwrap::<usize, String>(2);
-            let question_type = if question_type_string == "multiple choice" {
-                QuestionType::MultipleChoice
-            } else {
-                QuestionType::ShortAnswer
-            };
-            questions_map.insert(
-                id,
-                Question2 {
-                    id: id,
-                    text: question_text,
-                    question_type: question_type,
-                    answers: Vec::new(),
-                },
-            );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_missing_database_does_not_create_it() {
+        let path = std::env::temp_dir().join("drill_test_missing_database.db");
+        let _ = std::fs::remove_file(&path);
+
+        match load_quiz(&path) {
+            Err(QuizError::QuizNotFound(_)) => {}
+            other => panic!("expected QuizNotFound, got {:?}", other.map(|_| ())),
         }
+        assert!(!path.exists());
+    }
 
-        let mut question = questions_map.get_mut(&id).unwrap();
+    #[test]
+    fn migrated_answers_match_quiz() {
+        let output = std::env::temp_dir().join("drill_test_migrate_answers.db");
+        let _ = std::fs::remove_file(&output);
+        migrate_quiz(Path::new("tests/quizzes/migrate/migrate"), &output).unwrap();
+        let quiz = load_quiz(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
 
-        let answer_text = row.get_unwrap::<usize, String>(3);
-        let answer_correct = row.get_unwrap::<usize, i64>(4);
-        let answer_no_credit = row.get_unwrap::<usize, i64>(5);
-        question.answers.push(Answer2 {
-            variants: vec![answer_text],
-            correct: answer_correct != 0,
-            no_credit: answer_no_credit != 0,
-        });
+        // A flashcard keeps the context of each side.
+        let flashcard = &quiz.questions[0];
+        assert_eq!(flashcard.question_type, QuestionType::Flashcard);
+        assert_eq!(
+            flashcard.answers,
+            vec![
+                answer(&["to read"], true, false, Some("perf")),
+                answer(&["прочитать"], true, false, Some("bleh")),
+            ]
+        );
+
+        // A question from a choice group gets the other members of the group as its
+        // incorrect choices.
+        let choice = &quiz.questions[1];
+        assert_eq!(choice.question_type, QuestionType::MultipleChoice);
+        let mut answers = choice.answers.clone();
+        answers.sort_by(|a, b| a.variants.cmp(&b.variants));
+        assert_eq!(
+            answers,
+            vec![
+                answer(&["Atlanta"], true, false, None),
+                answer(&["Chicago"], false, false, None),
+                answer(&["Dallas"], false, false, None),
+            ]
+        );
+
+        // No-credit answers are kept, and marked as such.
+        let list = &quiz.questions[2];
+        assert_eq!(list.question_type, QuestionType::Unordered);
+        assert_eq!(
+            list.answers,
+            vec![
+                answer(&["San Bernardino"], true, false, None),
+                answer(&["Riverside"], true, false, None),
+                answer(&["Fontana"], true, false, None),
+                answer(&["Moreno Valley"], false, true, None),
+                answer(&["Corona"], false, true, None),
+            ]
+        );
     }
 
-    Ok(Quiz2 {
-        instructions: None,
-        version: String::from("1.0"),
-        // Courtesy of https://stackoverflow.com/questions/56724014
-        questions: questions_map.into_iter().map(|(_id, q)| q).collect(),
-    })
+    #[test]
+    fn migrated_questions_keep_their_ids() {
+        let output = std::env::temp_dir().join("drill_test_migrate_ids.db");
+        let _ = std::fs::remove_file(&output);
+        migrate_quiz(Path::new("tests/quizzes/migrate/migrate_ids"), &output).unwrap();
+        let quiz = load_quiz(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        let ids: Vec<i64> = quiz.questions.iter().map(|q| q.id).collect();
+        assert_eq!(ids, vec![7, 42]);
+        assert_eq!(quiz.questions[0].text, "What is the capital of Estonia?");
+        assert_eq!(quiz.questions[1].text, "What is the capital of Latvia?");
+    }
+
+    #[test]
+    fn databases_without_results_can_be_loaded() {
+        let path = std::env::temp_dir().join("drill_test_no_results.db");
+        let _ = std::fs::remove_file(&path);
+        {
+            let connection = Connection::open(&path).unwrap();
+            create_schema(&connection).unwrap();
+            connection
+                .execute_batch(
+                    "
+                    DROP TABLE results;
+                    INSERT INTO quizzes(name, instructions, version)
+                      VALUES ('drill_test_no_results', '', '1.0');
+                    INSERT INTO questions(quiz, text, type)
+                      VALUES (1, 'What is the capital of Malta?', 'short answer');
+                    INSERT INTO answers(question, text) VALUES (1, 'Valletta');
+                    ",
+                )
+                .unwrap();
+        }
+
+        let quiz = load_quiz(&path).unwrap();
+        assert_eq!(quiz.questions.len(), 1);
+        assert_eq!(quiz.questions[0].prior_results.len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn answer(variants: &[&str], correct: bool, no_credit: bool, context: Option<&str>) -> Answer2 {
+        Answer2 {
+            variants: variants.iter().map(|v| String::from(*v)).collect(),
+            correct,
+            no_credit,
+            context: context.map(String::from),
+        }
+    }
 }
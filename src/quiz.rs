@@ -4,22 +4,44 @@
  * Author:  Ian Fisher (iafisher@fastmail.com)
  * Version: October 2019
  */
+use std::cmp;
 use std::mem;
 
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use unicode_normalization::UnicodeNormalization;
 
-use super::common::{Location, QuizError, Result, TakeOptions};
+use super::common::{Clock, Location, QuizError, Result, SchedulerKind, TakeOptions};
+use super::fsrs;
+use super::journal::Journal;
+use super::quiz2::{Answer2, Question2, QuestionType};
 use super::repetition;
 use super::ui::CmdUI;
 
+/// With `--relearn`, how many other questions are asked before a missed question is
+/// asked again.
+const RELEARN_GAP: usize = 3;
+// How long, in milliseconds, the user can take to give each answer before a correct
+// answer counts as a slow one.
+const SLOW_ANSWER_MS: u64 = 10_000;
+
 /// Represents an entire quiz.
 #[derive(Debug)]
 pub struct Quiz {
     pub instructions: Option<String>,
     pub questions: Vec<Box<dyn Question>>,
+    /// The scheduler chosen in the quiz file, if any.
+    pub scheduler: Option<SchedulerKind>,
+    /// Parameters for the FSRS scheduler, fitted to this quiz's results.
+    pub fsrs_parameters: fsrs::Parameters,
+    /// How many lapses make a question a leech.
+    pub leech_threshold: usize,
+    /// Whether leeches are suspended instead of asked.
+    pub suspend_leeches: bool,
+    /// When the user's days begin and end.
+    pub calendar: repetition::Calendar,
 }
 
 impl Quiz {
@@ -28,30 +50,166 @@ impl Quiz {
         self.questions.iter().position(|q| q.get_common().id == id)
     }
 
-    pub fn take(&mut self, ui: &mut CmdUI, options: &TakeOptions) -> Result<QuizResult> {
+    /// Return the scheduler to use: `requested` if given (i.e., on the command line),
+    /// otherwise the one set in the quiz file, otherwise the bucket scheduler.
+    pub fn scheduler_kind(&self, requested: Option<SchedulerKind>) -> SchedulerKind {
+        requested
+            .or(self.scheduler)
+            .unwrap_or(SchedulerKind::Buckets)
+    }
+
+    /// Return the number of lapses at which questions are suspended, or `None` if
+    /// leeches are not suspended in this quiz.
+    pub fn suspend_after(&self) -> Option<usize> {
+        if self.suspend_leeches {
+            Some(self.leech_threshold)
+        } else {
+            None
+        }
+    }
+
+    /// Take the quiz, recording each result in `journal` as it is given. If the journal
+    /// continues an interrupted session, the questions of that session that have not
+    /// been answered yet are asked instead of choosing new ones.
+    pub fn take(
+        &mut self,
+        ui: &mut CmdUI,
+        options: &TakeOptions,
+        clock: &dyn Clock,
+        journal: &mut Journal,
+    ) -> Result<QuizResult> {
         if options.flip {
             for q in self.questions.iter_mut() {
                 q.flip();
             }
         }
 
-        let questions = repetition::choose_questions(&self.questions, &options);
+        let kind = self.scheduler_kind(options.scheduler);
+        let scheduler = repetition::get_scheduler(kind, &self.fsrs_parameters, self.calendar);
+        let now = clock.now();
+        let interrupted = journal.take_interrupted();
+        // Everything random in the session comes from one generator, so that the
+        // session can be reproduced from its seed.
+        let seed = if let Some(interrupted) = &interrupted {
+            interrupted.seed
+        } else {
+            options.seed.unwrap_or_else(|| thread_rng().gen())
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let questions = if let Some(interrupted) = &interrupted {
+            // Questions that were deleted since the session was interrupted are skipped.
+            interrupted
+                .questions
+                .iter()
+                .filter_map(|id| self.find(id))
+                .map(|i| &self.questions[i])
+                .collect()
+        } else {
+            repetition::choose_questions(
+                &self.questions,
+                scheduler.as_ref(),
+                &options,
+                self.suspend_after(),
+                now,
+                &mut rng,
+            )
+        };
         if questions.len() == 0 {
+            if options.due {
+                let next = repetition::next_due(
+                    &self.questions,
+                    scheduler.as_ref(),
+                    &options,
+                    self.suspend_after(),
+                    now,
+                );
+                if let Some(next) = next {
+                    ui.nothing_due(next - now)?;
+                    return Ok(QuizResult {
+                        time_finished: clock.now(),
+                        total: 0,
+                        total_correct: 0,
+                        total_partially_correct: 0,
+                        total_incorrect: 0,
+                        score: 0,
+                        per_question: Vec::new(),
+                        session: None,
+                    });
+                }
+            }
             return Err(QuizError::EmptyQuiz);
         }
 
+        let session = if let Some(interrupted) = &interrupted {
+            interrupted.session.clone()
+        } else {
+            let session = Session::new(options, seed, now);
+            let ids: Vec<&str> = questions
+                .iter()
+                .map(|q| q.get_common().id.as_str())
+                .collect();
+            journal.start(&ids, &session)?;
+            Some(session)
+        };
+
         if let Some(instructions) = &self.instructions {
             ui.instructions(&instructions)?;
         }
 
-        let mut results = Vec::new();
-        let mut index = 0;
+        // The questions to ask, as indices into `questions`. With `--relearn`, a question
+        // that is missed is added back to the queue, and with `--cram`, a question is
+        // added back until its streak of correct answers is long enough.
+        let mut results: Vec<QuestionResult> = interrupted
+            .map(|interrupted| interrupted.results)
+            .unwrap_or_else(Vec::new);
+        // The questions that were already answered in an interrupted session go at the
+        // front of the queue, so that they are not asked again.
+        let mut queue: Vec<usize> = Vec::new();
+        for result in results.iter().filter(|r| r.practice != Some(true)) {
+            let position = questions
+                .iter()
+                .position(|q| q.get_common().id == result.id);
+            if let Some(i) = position {
+                if !queue.contains(&i) {
+                    queue.push(i);
+                }
+            }
+        }
+        let mut index = queue.len();
+        let mut asked = vec![false; questions.len()];
+        for i in queue.iter() {
+            asked[*i] = true;
+        }
+        queue.extend((0..questions.len()).filter(|i| !asked[*i]));
+        if index > 0 {
+            ui.status(&format!(
+                "Resuming interrupted session ({} of {} questions left).",
+                queue.len() - index,
+                queue.len()
+            ))?;
+        }
         ui.next();
-        while index < questions.len() {
-            let result = questions[index].ask(ui);
+        while index < queue.len() {
+            // Time the answer from when the question is printed, so that the time spent
+            // on commands like `!edit` isn't counted.
+            ui.restart_timer();
+            let result = questions[queue[index]].ask(ui, &mut rng, clock);
             match result {
-                Ok(result) => {
+                Ok(mut result) => {
+                    result.seed = Some(seed);
+                    result.session = session.as_ref().map(|s| s.id.clone());
+                    result.response_time_ms = Some(ui.elapsed().as_millis() as u64);
+                    if asked[queue[index]] {
+                        result.practice = Some(true);
+                    }
+                    asked[queue[index]] = true;
                     results.push(result);
+                    journal.record(results.len() - 1, &results[results.len() - 1])?;
+
+                    if needs_another_attempt(&results, options) {
+                        let position = cmp::min(index + 1 + RELEARN_GAP, queue.len());
+                        queue.insert(position, queue[index]);
+                    }
                 }
                 Err(QuizError::ReadlineInterrupted) => {
                     break;
@@ -61,6 +219,14 @@ impl Quiz {
                         let last = results.len() - 1;
                         if results[last].score < 1000 {
                             results[last].score = 1000;
+                            journal.record(last, &results[last])?;
+                            let prev = queue[index - 1];
+                            if !needs_another_attempt(&results, options) {
+                                // The question no longer needs to be asked again.
+                                if let Some(pos) = queue[index..].iter().position(|i| *i == prev) {
+                                    queue.remove(index + pos);
+                                }
+                            }
                             ui.status("Previous answer marked correct.")?;
                         } else {
                             ui.status("Previous answer was already correct.")?;
@@ -73,7 +239,7 @@ impl Quiz {
                 }
                 Err(QuizError::SignalEdit) => {
                     if index > 0 {
-                        let prev = &questions[index - 1];
+                        let prev = &questions[queue[index - 1]];
                         ui.launch_editor(&prev.get_common().location)?;
                         ui.status(
                             "Edited previous question. Enter !! to mark your answer correct.",
@@ -84,6 +250,39 @@ impl Quiz {
                     // Continue asking the same question.
                     continue;
                 }
+                Err(QuizError::SignalSuspend) => {
+                    if let Some(last) = last_scored_result(&results) {
+                        results[last].suspended = Some(true);
+                        journal.record(last, &results[last])?;
+                        ui.status("Previous question suspended until it is rewritten.")?;
+                    } else {
+                        ui.status("No previous question to suspend.")?;
+                    }
+                    // Continue asking the same question.
+                    continue;
+                }
+                Err(QuizError::SignalBury) => {
+                    if let Some(last) = last_scored_result(&results) {
+                        results[last].buried_until = Some(self.calendar.start_of_next_day(now));
+                        journal.record(last, &results[last])?;
+                        ui.status("Previous question buried until tomorrow.")?;
+                    } else {
+                        ui.status("No previous question to bury.")?;
+                    }
+                    // Continue asking the same question.
+                    continue;
+                }
+                Err(QuizError::SignalFlag(note)) => {
+                    if let Some(last) = last_scored_result(&results) {
+                        results[last].flag = Some(note);
+                        journal.record(last, &results[last])?;
+                        ui.status("Previous question flagged.")?;
+                    } else {
+                        ui.status("No previous question to flag.")?;
+                    }
+                    // Continue asking the same question.
+                    continue;
+                }
                 Err(e) => {
                     return Err(e);
                 }
@@ -92,38 +291,42 @@ impl Quiz {
             ui.next();
         }
 
-        let total = results.len();
-        let aggregate_score: u64 = results.iter().map(|r| r.score).sum();
-        let total_correct = results.iter().filter(|r| r.score == 1000).count();
-        let total_partially_correct = results
-            .iter()
-            .filter(|r| r.score < 1000 && r.score > 0)
-            .count();
-        let total_incorrect = total - total_correct - total_partially_correct;
-        let score = if total > 0 {
-            aggregate_score / total as u64
-        } else {
-            0
-        };
-        let ret = QuizResult {
-            time_finished: chrono::Utc::now(),
-            total,
-            total_correct,
-            total_partially_correct,
-            total_incorrect,
-            score,
-            per_question: results,
-        };
+        let ret = QuizResult::from_results(results, session, clock.now());
         ui.results(&ret)?;
+
+        if let Some(n) = options.cram {
+            let mut mastery: Vec<(usize, bool, String)> = questions
+                .iter()
+                .map(|q| {
+                    let id = &q.get_common().id;
+                    let attempts = ret.per_question.iter().filter(|r| &r.id == id).count();
+                    (attempts, streak(&ret.per_question, id) >= n, q.get_text())
+                })
+                .collect();
+            mastery.sort_by(|a, b| b.0.cmp(&a.0));
+            ui.mastery(&mastery)?;
+        }
         Ok(ret)
     }
 }
 
 pub trait Question: std::fmt::Debug {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult>;
+    /// Ask the question. `rng` is the session's random number generator, for questions
+    /// that need to shuffle anything, and `clock` gives the time the question was asked.
+    fn ask(&self, ui: &mut CmdUI, rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult>;
     fn get_common(&self) -> &QuestionCommon;
+    fn get_common_mut(&mut self) -> &mut QuestionCommon;
     fn get_text(&self) -> String;
+    /// Return whether `text`, as recorded in a result, matches how the question is
+    /// currently worded. A mismatch means the question was rewritten after the result
+    /// was recorded.
+    fn has_text(&self, text: &str) -> bool {
+        text == self.get_text()
+    }
     fn flip(&mut self) {}
+    /// Convert the question into the representation used by the SQLite backend. The
+    /// `id` field of the returned question is not assigned until it is saved.
+    fn to_question2(&self) -> Question2;
 }
 
 #[derive(Debug, Clone)]
@@ -131,6 +334,9 @@ pub struct QuestionCommon {
     pub id: String,
     pub prior_results: Vec<QuestionResult>,
     pub tags: Vec<String>,
+    /// IDs of questions that must be asked before this one, if they are asked in the
+    /// same session.
+    pub after: Vec<String>,
     pub location: Location,
 }
 
@@ -142,7 +348,7 @@ pub struct ShortAnswerQuestion {
 }
 
 impl Question for ShortAnswerQuestion {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult> {
+    fn ask(&self, ui: &mut CmdUI, _rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult> {
         ui.text(&self.text)?;
         if let Some(guess) = ui.prompt()? {
             if check(&self.answer, &guess) {
@@ -153,23 +359,48 @@ impl Question for ShortAnswerQuestion {
                     &self.text,
                     Some(guess),
                     score,
+                    clock.now(),
                 ))
             } else {
                 ui.incorrect(Some(&self.answer[0]))?;
-                Ok(mkresult(&self.get_common().id, &self.text, Some(guess), 0))
+                Ok(mkresult(
+                    &self.get_common().id,
+                    &self.text,
+                    Some(guess),
+                    0,
+                    clock.now(),
+                ))
             }
         } else {
             ui.incorrect(Some(&self.answer[0]))?;
-            Ok(mkresult(&self.get_common().id, &self.text, None, 0))
+            Ok(mkresult(
+                &self.get_common().id,
+                &self.text,
+                None,
+                0,
+                clock.now(),
+            ))
         }
     }
 
     fn get_common(&self) -> &QuestionCommon {
         &self.common
     }
+    fn get_common_mut(&mut self) -> &mut QuestionCommon {
+        &mut self.common
+    }
     fn get_text(&self) -> String {
         self.text.clone()
     }
+
+    fn to_question2(&self) -> Question2 {
+        mkquestion2(
+            &self.common,
+            &self.text,
+            QuestionType::ShortAnswer,
+            vec![mkanswer2(&self.answer, None, true, false)],
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -182,7 +413,7 @@ pub struct FlashcardQuestion {
 }
 
 impl Question for FlashcardQuestion {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult> {
+    fn ask(&self, ui: &mut CmdUI, _rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult> {
         let text = if let Some(context) = &self.front_context {
             format!("{} [{}]", self.front[0], context)
         } else {
@@ -194,28 +425,71 @@ impl Question for FlashcardQuestion {
             if check(&self.back, &guess) {
                 ui.correct()?;
                 let score = 1000;
-                Ok(mkresult(&self.get_common().id, &text, Some(guess), score))
+                Ok(mkresult(
+                    &self.get_common().id,
+                    &text,
+                    Some(guess),
+                    score,
+                    clock.now(),
+                ))
             } else {
                 ui.incorrect(Some(&self.back[0]))?;
-                Ok(mkresult(&self.get_common().id, &text, Some(guess), 0))
+                Ok(mkresult(
+                    &self.get_common().id,
+                    &text,
+                    Some(guess),
+                    0,
+                    clock.now(),
+                ))
             }
         } else {
             ui.incorrect(Some(&self.back[0]))?;
-            Ok(mkresult(&self.get_common().id, &text, None, 0))
+            Ok(mkresult(&self.get_common().id, &text, None, 0, clock.now()))
         }
     }
 
     fn get_common(&self) -> &QuestionCommon {
         &self.common
     }
+    fn get_common_mut(&mut self) -> &mut QuestionCommon {
+        &mut self.common
+    }
     fn get_text(&self) -> String {
         self.front[0].clone()
     }
 
+    fn has_text(&self, text: &str) -> bool {
+        // The recorded text includes the context, and the card may have been flipped.
+        let sides = [
+            (&self.front[0], &self.front_context),
+            (&self.back[0], &self.back_context),
+        ];
+        sides.iter().any(|(side, context)| {
+            if let Some(context) = context {
+                text == format!("{} [{}]", side, context)
+            } else {
+                text == *side
+            }
+        })
+    }
+
     fn flip(&mut self) {
         mem::swap(&mut self.front, &mut self.back);
         mem::swap(&mut self.front_context, &mut self.back_context);
     }
+
+    fn to_question2(&self) -> Question2 {
+        let text = if let Some(context) = &self.front_context {
+            format!("{} [{}]", self.front[0], context)
+        } else {
+            self.front[0].clone()
+        };
+        let answers = vec![
+            mkanswer2(&self.front, self.front_context.clone(), true, false),
+            mkanswer2(&self.back, self.back_context.clone(), true, false),
+        ];
+        mkquestion2(&self.common, &text, QuestionType::Flashcard, answers)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -227,7 +501,7 @@ pub struct ListQuestion {
 }
 
 impl Question for ListQuestion {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult> {
+    fn ask(&self, ui: &mut CmdUI, _rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult> {
         let n = self.answer_list.len();
         let mut satisfied = vec![false; n];
 
@@ -300,15 +574,31 @@ impl Question for ListQuestion {
             &self.text,
             responses,
             score,
+            clock.now(),
         ))
     }
 
     fn get_common(&self) -> &QuestionCommon {
         &self.common
     }
+    fn get_common_mut(&mut self) -> &mut QuestionCommon {
+        &mut self.common
+    }
     fn get_text(&self) -> String {
         self.text.clone()
     }
+
+    fn to_question2(&self) -> Question2 {
+        let mut answers: Vec<Answer2> = self
+            .answer_list
+            .iter()
+            .map(|a| mkanswer2(a, None, true, false))
+            .collect();
+        for no_credit in self.no_credit.iter() {
+            answers.push(mkanswer2(&vec![no_credit.clone()], None, false, true));
+        }
+        mkquestion2(&self.common, &self.text, QuestionType::Unordered, answers)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -320,7 +610,7 @@ pub struct OrderedListQuestion {
 }
 
 impl Question for OrderedListQuestion {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult> {
+    fn ask(&self, ui: &mut CmdUI, _rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult> {
         ui.text(&self.text)?;
 
         let mut index = 0;
@@ -372,15 +662,31 @@ impl Question for OrderedListQuestion {
             &self.text,
             responses,
             score,
+            clock.now(),
         ))
     }
 
     fn get_common(&self) -> &QuestionCommon {
         &self.common
     }
+    fn get_common_mut(&mut self) -> &mut QuestionCommon {
+        &mut self.common
+    }
     fn get_text(&self) -> String {
         self.text.clone()
     }
+
+    fn to_question2(&self) -> Question2 {
+        let mut answers: Vec<Answer2> = self
+            .answer_list
+            .iter()
+            .map(|a| mkanswer2(a, None, true, false))
+            .collect();
+        for no_credit in self.no_credit.iter() {
+            answers.push(mkanswer2(&vec![no_credit.clone()], None, false, true));
+        }
+        mkquestion2(&self.common, &self.text, QuestionType::Ordered, answers)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -392,19 +698,18 @@ pub struct MultipleChoiceQuestion {
 }
 
 impl Question for MultipleChoiceQuestion {
-    fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult> {
+    fn ask(&self, ui: &mut CmdUI, rng: &mut StdRng, clock: &dyn Clock) -> Result<QuestionResult> {
         ui.text(&self.text)?;
 
         let mut choices: Vec<&str> = self.choices.iter().map(|s| s.as_str()).collect();
-        let mut rng = thread_rng();
         // Shuffle once so that we don't always pick the first three candidates listed.
-        choices.shuffle(&mut rng);
+        choices.shuffle(rng);
         choices.truncate(3);
 
-        let answer = self.answer.choose(&mut rng).unwrap();
+        let answer = self.answer.choose(rng).unwrap();
         choices.push(&answer);
         // Shuffle again so that the position of the correct answer is random.
-        choices.shuffle(&mut rng);
+        choices.shuffle(rng);
 
         ui.choices(&choices)?;
         let mut response = None;
@@ -437,15 +742,37 @@ impl Question for MultipleChoiceQuestion {
             }
         }
         let score = if correct { 1000 } else { 0 };
-        Ok(mkresult(&self.get_common().id, &self.text, response, score))
+        Ok(mkresult(
+            &self.get_common().id,
+            &self.text,
+            response,
+            score,
+            clock.now(),
+        ))
     }
 
     fn get_common(&self) -> &QuestionCommon {
         &self.common
     }
+    fn get_common_mut(&mut self) -> &mut QuestionCommon {
+        &mut self.common
+    }
     fn get_text(&self) -> String {
         self.text.clone()
     }
+
+    fn to_question2(&self) -> Question2 {
+        let mut answers = vec![mkanswer2(&self.answer, None, true, false)];
+        for choice in self.choices.iter() {
+            answers.push(mkanswer2(&vec![choice.clone()], None, false, false));
+        }
+        mkquestion2(
+            &self.common,
+            &self.text,
+            QuestionType::MultipleChoice,
+            answers,
+        )
+    }
 }
 
 /// Each member of the vector should be an equivalent answer, e.g.
@@ -477,6 +804,103 @@ pub struct QuestionResult {
     pub score: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timed_out: Option<bool>,
+    /// Set if the user suspended the question after answering it. A suspended question
+    /// is not asked again until it is rewritten.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspended: Option<bool>,
+    /// Set if the user buried the question after answering it, to the time until which
+    /// it will not be asked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buried_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// A note left by the user about the question, e.g. to point out a mistake.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    /// Set if the question had already been asked earlier in the same session, in
+    /// which case the result does not count towards the question's score or schedule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub practice: Option<bool>,
+    /// The seed of the random number generator for the session, which can be passed to
+    /// `--seed` to ask the questions in the same order with the same choices.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// How long the user took to answer, in milliseconds. Optional for backwards
+    /// compatibility.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_time_ms: Option<u64>,
+    /// The ID of the session in which the question was asked. Optional for backwards
+    /// compatibility.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+}
+
+impl QuestionResult {
+    /// Construct a result with only the required fields filled in.
+    pub fn new(id: &str, time_asked: chrono::DateTime<chrono::Utc>, score: u64) -> Self {
+        QuestionResult {
+            id: String::from(id),
+            text: None,
+            time_asked,
+            response: None,
+            response_list: None,
+            score,
+            timed_out: None,
+            suspended: None,
+            buried_until: None,
+            flag: None,
+            practice: None,
+            seed: None,
+            response_time_ms: None,
+            session: None,
+        }
+    }
+
+    /// Return the score that a scheduler should use for this result. A correct answer
+    /// that the user was slow to give is capped at `slow_score`, each scheduler's idea
+    /// of weaker recall than a fast one.
+    pub fn recall_score(&self, slow_score: u64) -> u64 {
+        if let Some(ms) = self.response_time_ms {
+            let answers = self.response_list.as_ref().map(|l| l.len()).unwrap_or(1);
+            if ms > SLOW_ANSWER_MS * cmp::max(answers, 1) as u64 {
+                return cmp::min(self.score, slow_score);
+            }
+        }
+        self.score
+    }
+}
+
+/// Describes a single session of a quiz: when it started and how the questions were
+/// chosen.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    /// A random identifier, which each result of the session is linked to.
+    pub id: String,
+    pub time_started: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    pub num_to_ask: usize,
+    #[serde(default)]
+    pub flip: bool,
+    pub seed: u64,
+}
+
+impl Session {
+    pub fn new(
+        options: &TakeOptions,
+        seed: u64,
+        time_started: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Session {
+            id: format!("{:016x}", thread_rng().gen::<u64>()),
+            time_started,
+            tags: options.filter_opts.tags.clone(),
+            exclude: options.filter_opts.exclude.clone(),
+            num_to_ask: options.num_to_ask,
+            flip: options.flip,
+            seed,
+        }
+    }
 }
 
 /// Represents the results of taking a quiz on a particular occasion.
@@ -489,7 +913,48 @@ pub struct QuizResult {
     pub total_incorrect: usize,
     /// Score out of 1,000 possible points.
     pub score: u64,
+    /// Every answer given in the session, in order, including practice attempts.
     pub per_question: Vec<QuestionResult>,
+    /// The session that the results are from, or `None` if it was not recorded.
+    pub session: Option<Session>,
+}
+
+impl QuizResult {
+    /// Summarize the answers given in a session.
+    pub fn from_results(
+        per_question: Vec<QuestionResult>,
+        session: Option<Session>,
+        time_finished: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        // Only the first attempt at each question counts towards the score.
+        let scored: Vec<&QuestionResult> = per_question
+            .iter()
+            .filter(|r| r.practice != Some(true))
+            .collect();
+        let total = scored.len();
+        let aggregate_score: u64 = scored.iter().map(|r| r.score).sum();
+        let total_correct = scored.iter().filter(|r| r.score == 1000).count();
+        let total_partially_correct = scored
+            .iter()
+            .filter(|r| r.score < 1000 && r.score > 0)
+            .count();
+        let total_incorrect = total - total_correct - total_partially_correct;
+        let score = if total > 0 {
+            aggregate_score / total as u64
+        } else {
+            0
+        };
+        QuizResult {
+            time_finished,
+            total,
+            total_correct,
+            total_partially_correct,
+            total_incorrect,
+            score,
+            per_question,
+            session,
+        }
+    }
 }
 
 /// Return the index of the first answer in `answer_list` that `guess` matches, or
@@ -518,28 +983,102 @@ fn normalize(guess: &str) -> String {
 }
 
 /// Construct a `QuestionResult` object.
-fn mkresult(id: &str, text: &str, response: Option<String>, score: u64) -> QuestionResult {
+fn mkresult(
+    id: &str,
+    text: &str,
+    response: Option<String>,
+    score: u64,
+    time_asked: chrono::DateTime<chrono::Utc>,
+) -> QuestionResult {
     QuestionResult {
-        id: String::from(id),
         text: Some(String::from(text)),
-        time_asked: chrono::Utc::now(),
-        score,
         response,
-        response_list: None,
-        timed_out: None,
+        ..QuestionResult::new(id, time_asked, score)
+    }
+}
+
+/// Return whether the last question answered in `results` should be asked again later
+/// in the session, because of `--relearn` or `--cram`.
+fn needs_another_attempt(results: &Vec<QuestionResult>, options: &TakeOptions) -> bool {
+    if let Some(last) = results.last() {
+        // A question that the user skipped without answering (e.g. with Ctrl+D) is not
+        // asked again.
+        let skipped = last.response.is_none()
+            && last
+                .response_list
+                .as_ref()
+                .map(|l| l.is_empty())
+                .unwrap_or(true);
+        if skipped {
+            false
+        } else if let Some(n) = options.cram {
+            streak(results, &last.id) < n
+        } else {
+            options.relearn && last.score < 1000
+        }
+    } else {
+        false
     }
 }
 
+/// Return the number of times in a row that the question with the given ID has been
+/// answered correctly at the end of `results`.
+fn streak(results: &Vec<QuestionResult>, id: &str) -> usize {
+    results
+        .iter()
+        .rev()
+        .filter(|r| r.id == id)
+        .take_while(|r| r.score == 1000)
+        .count()
+}
+
+/// Return the index of the result of the first attempt at the last question answered
+/// in `results`. Since practice attempts are ignored when the results are loaded, this
+/// is where the user's commands about the previous question must be recorded.
+fn last_scored_result(results: &Vec<QuestionResult>) -> Option<usize> {
+    let id = &results.last()?.id;
+    results
+        .iter()
+        .rposition(|r| &r.id == id && r.practice != Some(true))
+}
+
 /// Construct a `QuestionResult` object with a list of responses.
-fn mkresultlist(id: &str, text: &str, responses: Vec<String>, score: u64) -> QuestionResult {
+fn mkresultlist(
+    id: &str,
+    text: &str,
+    responses: Vec<String>,
+    score: u64,
+    time_asked: chrono::DateTime<chrono::Utc>,
+) -> QuestionResult {
     QuestionResult {
-        id: String::from(id),
         text: Some(String::from(text)),
-        time_asked: chrono::Utc::now(),
-        score,
-        response: None,
         response_list: Some(responses),
-        timed_out: None,
+        ..QuestionResult::new(id, time_asked, score)
+    }
+}
+
+fn mkquestion2(
+    common: &QuestionCommon,
+    text: &str,
+    question_type: QuestionType,
+    answers: Vec<Answer2>,
+) -> Question2 {
+    Question2 {
+        id: 0,
+        text: String::from(text),
+        question_type,
+        answers,
+        tags: common.tags.clone(),
+        prior_results: Vec::new(),
+    }
+}
+
+fn mkanswer2(answer: &Answer, context: Option<String>, correct: bool, no_credit: bool) -> Answer2 {
+    Answer2 {
+        variants: answer.clone(),
+        correct,
+        no_credit,
+        context,
     }
 }
 
@@ -1,3 +1,5 @@
+use rand::seq::SliceRandom;
+use rand::thread_rng;
 use unicode_normalization::UnicodeNormalization;
 
 use super::common::{QuizError, Result, TakeOptions};
@@ -17,9 +19,12 @@ pub struct Question2 {
     pub text: String,
     pub question_type: QuestionType,
     pub answers: Vec<Answer2>,
+    pub tags: Vec<String>,
+    /// Results of previous sessions, in chronological order.
+    pub prior_results: Vec<QuestionResult2>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum QuestionType {
     ShortAnswer,
     Ordered,
@@ -28,11 +33,15 @@ pub enum QuestionType {
     Flashcard,
 }
 
-#[derive(Debug)]
+/// For flashcards, the first answer is the front of the card and the second answer is
+/// the back.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Answer2 {
     pub variants: Vec<String>,
     pub correct: bool,
     pub no_credit: bool,
+    /// Context displayed next to a side of a flashcard, e.g. to disambiguate it.
+    pub context: Option<String>,
 }
 
 /// Represents the result of answering a question on a particular occasion.
@@ -51,6 +60,9 @@ pub struct QuestionResult2 {
     pub response_list: Option<Vec<String>>,
     /// Score out of 1,000 possible points.
     pub score: u64,
+    /// The session in which the question was asked. `None` for results that have not
+    /// been saved yet, and for results migrated from the text format.
+    pub session: Option<i64>,
 }
 
 /// Represents the results of taking a quiz on a particular occasion.
@@ -68,6 +80,12 @@ pub struct QuizResult2 {
 
 impl Quiz2 {
     pub fn take(&mut self, ui: &mut CmdUI, options: &TakeOptions) -> Result<QuizResult2> {
+        if options.flip {
+            for q in self.questions.iter_mut() {
+                q.flip();
+            }
+        }
+
         let questions = repetition2::choose_questions(&self.questions, &options);
         if questions.len() == 0 {
             return Err(QuizError::EmptyQuiz);
@@ -141,21 +159,274 @@ impl Quiz2 {
 
 impl Question2 {
     fn ask(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        self.validate()?;
+        match self.question_type {
+            QuestionType::ShortAnswer => self.ask_short_answer(ui),
+            QuestionType::Ordered => self.ask_ordered(ui),
+            QuestionType::Unordered => self.ask_unordered(ui),
+            QuestionType::MultipleChoice => self.ask_multiple_choice(ui),
+            QuestionType::Flashcard => self.ask_flashcard(ui),
+        }
+    }
+
+    /// Check that the question has the answers that asking it requires, since the
+    /// database does not enforce this.
+    fn validate(&self) -> Result<()> {
+        let malformed = |message: &str| QuizError::MalformedQuestion {
+            id: self.id,
+            message: String::from(message),
+        };
+
+        if self.answers.iter().any(|a| a.variants.len() == 0) {
+            return Err(malformed("answer has no variants"));
+        }
+
+        if self.question_type == QuestionType::Flashcard {
+            if self.answers.len() < 2 {
+                return Err(malformed("flashcard does not have two sides"));
+            }
+        } else if self.correct_answers().len() == 0 {
+            return Err(malformed("no correct answer"));
+        }
+        Ok(())
+    }
+
+    /// Swap the front and back of a flashcard. Other kinds of questions are unaffected.
+    pub fn flip(&mut self) {
+        if self.question_type == QuestionType::Flashcard && self.answers.len() >= 2 {
+            self.answers.swap(0, 1);
+        }
+    }
+
+    fn ask_short_answer(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        let answers = self.correct_answers();
         ui.text(&self.text)?;
         if let Some(guess) = ui.prompt()? {
-            if check(&self.answers[0], &guess) {
+            if check_one(&answers, &guess).is_some() {
                 ui.correct()?;
                 let score = 1000;
                 Ok(mkresult(self.id, &self.text, Some(guess), score))
             } else {
-                ui.incorrect(Some(&self.answers[0].variants[0]))?;
+                ui.incorrect(Some(&answers[0].variants[0]))?;
                 Ok(mkresult(self.id, &self.text, Some(guess), 0))
             }
         } else {
-            ui.incorrect(Some(&self.answers[0].variants[0]))?;
+            ui.incorrect(Some(&answers[0].variants[0]))?;
             Ok(mkresult(self.id, &self.text, None, 0))
         }
     }
+
+    fn ask_flashcard(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        let front = &self.answers[0];
+        let back = &self.answers[1];
+        let text = if let Some(context) = &front.context {
+            format!("{} [{}]", front.variants[0], context)
+        } else {
+            front.variants[0].clone()
+        };
+        ui.text(&text)?;
+
+        if let Some(guess) = ui.prompt()? {
+            if check(back, &guess) {
+                ui.correct()?;
+                let score = 1000;
+                Ok(mkresult(self.id, &text, Some(guess), score))
+            } else {
+                ui.incorrect(Some(&back.variants[0]))?;
+                Ok(mkresult(self.id, &text, Some(guess), 0))
+            }
+        } else {
+            ui.incorrect(Some(&back.variants[0]))?;
+            Ok(mkresult(self.id, &text, None, 0))
+        }
+    }
+
+    fn ask_unordered(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        let answer_list = self.correct_answers();
+        let no_credit = self.no_credit_answers();
+        let n = answer_list.len();
+        let mut satisfied = vec![false; n];
+
+        ui.text(&self.text)?;
+        let mut responses = Vec::new();
+        while responses.len() < n {
+            match ui.prompt() {
+                Ok(Some(guess)) => {
+                    if let Some(index) = check_one(&answer_list, &guess) {
+                        if satisfied[index] {
+                            ui.status("You already said that.")?;
+                        } else {
+                            satisfied[index] = true;
+                            responses.push(guess.clone());
+                            ui.correct()?;
+                        }
+                    } else {
+                        if check_one(&no_credit, &guess).is_some() {
+                            ui.status("No credit.")?;
+                        } else {
+                            responses.push(guess.clone());
+                            ui.incorrect(None)?;
+                        }
+                    }
+                }
+                Ok(None) => {
+                    ui.incorrect(None)?;
+                    break;
+                }
+                Err(QuizError::SignalMarkCorrect) => {
+                    if let Some(last) = responses.last().as_ref() {
+                        if check_one(&answer_list, last).is_none() {
+                            // We can't actually mark the answer correct without knowing
+                            // which real answer it was meant to match, so instead we
+                            // just undo the answer.
+                            responses.pop();
+                            ui.status("Previous answer undone.")?;
+                        } else {
+                            ui.status("Previous answer was already correct.")?;
+                        }
+                    } else {
+                        // If there was no previous answer to this question, then we
+                        // propagate the error upwards so that the previous question
+                        // can be corrected.
+                        return Err(QuizError::SignalMarkCorrect);
+                    }
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut missed = Vec::new();
+        for (i, b) in satisfied.iter().enumerate() {
+            if !b {
+                missed.push(answer_list[i].variants[0].as_str());
+            }
+        }
+
+        if missed.len() > 0 {
+            ui.missed(&missed)?;
+        }
+        let score = (n - missed.len()) as f64 / (n as f64);
+        let score = (score * 1000.0) as u64;
+        ui.score(score)?;
+
+        Ok(mkresultlist(self.id, &self.text, responses, score))
+    }
+
+    fn ask_ordered(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        let answer_list = self.correct_answers();
+        ui.text(&self.text)?;
+
+        let mut index = 0;
+        let mut ncorrect = 0;
+        let mut responses = Vec::new();
+        while index < answer_list.len() {
+            let answer = answer_list[index];
+            match ui.prompt() {
+                Ok(Some(guess)) => {
+                    responses.push(guess.clone());
+
+                    if check(answer, &guess) {
+                        ui.correct()?;
+                        ncorrect += 1;
+                    } else {
+                        ui.incorrect(Some(&answer.variants[0]))?;
+                    }
+                    index += 1;
+                }
+                Ok(None) => {
+                    ui.incorrect(Some(&answer.variants[0]))?;
+                    break;
+                }
+                Err(QuizError::SignalMarkCorrect) => {
+                    if let Some(last) = responses.last().as_ref() {
+                        if check_one(&answer_list, last).is_none() {
+                            ncorrect += 1;
+                            ui.status("Previous answer marked correct.")?;
+                        } else {
+                            ui.status("Previous answer was already correct.")?;
+                        }
+                    } else {
+                        // If there was no previous answer to this question, then we
+                        // propagate the error upwards so that the previous question
+                        // can be corrected.
+                        return Err(QuizError::SignalMarkCorrect);
+                    }
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+        let score = (ncorrect as f64) / (answer_list.len() as f64);
+        let score = (score * 1000.0) as u64;
+        ui.score(score)?;
+        Ok(mkresultlist(self.id, &self.text, responses, score))
+    }
+
+    fn ask_multiple_choice(&self, ui: &mut CmdUI) -> Result<QuestionResult2> {
+        ui.text(&self.text)?;
+
+        let correct = self.correct_answers()[0];
+        let mut choices: Vec<&str> = self
+            .answers
+            .iter()
+            .filter(|a| !a.correct && !a.no_credit)
+            .flat_map(|a| a.variants.iter().map(|v| v.as_str()))
+            .collect();
+        let mut rng = thread_rng();
+        // Shuffle once so that we don't always pick the first three candidates listed.
+        choices.shuffle(&mut rng);
+        choices.truncate(3);
+
+        let answer = correct.variants.choose(&mut rng).unwrap();
+        choices.push(&answer);
+        // Shuffle again so that the position of the correct answer is random.
+        choices.shuffle(&mut rng);
+
+        ui.choices(&choices)?;
+        let mut response = None;
+        let mut score = 0;
+        loop {
+            if let Some(guess) = ui.prompt()? {
+                if guess.len() != 1 {
+                    ui.status("Please enter a letter.")?;
+                    continue;
+                }
+
+                let index = guess.to_ascii_lowercase().as_bytes()[0];
+                if 97 <= index && index < 97 + (choices.len() as u8) {
+                    let guess = choices[(index - 97) as usize];
+                    response.replace(String::from(guess));
+                    if check(correct, guess) {
+                        ui.correct()?;
+                        score = 1000;
+                    } else {
+                        ui.incorrect(Some(&answer))?;
+                    }
+                    break;
+                } else {
+                    ui.status("Please enter a letter.")?;
+                    continue;
+                }
+            } else {
+                ui.incorrect(Some(&answer))?;
+                break;
+            }
+        }
+        Ok(mkresult(self.id, &self.text, response, score))
+    }
+
+    /// Return the answers that count towards the question's score, in order.
+    fn correct_answers(&self) -> Vec<&Answer2> {
+        self.answers.iter().filter(|a| a.correct).collect()
+    }
+
+    /// Return the answers that count as neither correct nor incorrect.
+    fn no_credit_answers(&self) -> Vec<&Answer2> {
+        self.answers.iter().filter(|a| a.no_credit).collect()
+    }
 }
 
 /// Construct a `QuestionResult` object.
@@ -167,6 +438,7 @@ fn mkresult(id: i64, text: &str, response: Option<String>, score: u64) -> Questi
         score,
         response,
         response_list: None,
+        session: None,
     }
 }
 
@@ -179,12 +451,13 @@ fn mkresultlist(id: i64, text: &str, responses: Vec<String>, score: u64) -> Ques
         score,
         response: None,
         response_list: Some(responses),
+        session: None,
     }
 }
 
 /// Return the index of the first answer in `answer_list` that `guess` matches, or
 /// `None` if `guess` satisfies none.
-pub fn check_one(answer_list: &Vec<Answer2>, guess: &str) -> Option<usize> {
+pub fn check_one(answer_list: &Vec<&Answer2>, guess: &str) -> Option<usize> {
     for (i, answer) in answer_list.iter().enumerate() {
         if check(answer, guess) {
             return Some(i);
@@ -206,3 +479,79 @@ pub fn check(ans: &Answer2, guess: &str) -> bool {
 fn normalize(guess: &str) -> String {
     String::from(guess.to_lowercase()).nfc().collect::<String>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_answer_without_correct_answer_is_an_error() {
+        let q = question(QuestionType::ShortAnswer, vec![answer("Valletta", false)]);
+        assert_malformed(&q, "no correct answer");
+    }
+
+    #[test]
+    fn ordered_without_correct_answer_is_an_error() {
+        let q = question(QuestionType::Ordered, vec![]);
+        assert_malformed(&q, "no correct answer");
+    }
+
+    #[test]
+    fn unordered_without_correct_answer_is_an_error() {
+        let q = question(QuestionType::Unordered, vec![answer("Gozo", false)]);
+        assert_malformed(&q, "no correct answer");
+    }
+
+    #[test]
+    fn multiple_choice_without_correct_answer_is_an_error() {
+        let q = question(
+            QuestionType::MultipleChoice,
+            vec![answer("Mdina", false), answer("Rabat", false)],
+        );
+        assert_malformed(&q, "no correct answer");
+    }
+
+    #[test]
+    fn flashcard_without_back_is_an_error() {
+        let q = question(QuestionType::Flashcard, vec![answer("to read", true)]);
+        assert_malformed(&q, "flashcard does not have two sides");
+    }
+
+    #[test]
+    fn answer_without_variants_is_an_error() {
+        let mut empty = answer("Valletta", true);
+        empty.variants.clear();
+        let q = question(QuestionType::ShortAnswer, vec![empty]);
+        assert_malformed(&q, "answer has no variants");
+    }
+
+    fn assert_malformed(q: &Question2, expected: &str) {
+        match q.ask(&mut CmdUI::new()) {
+            Err(QuizError::MalformedQuestion { id, message }) => {
+                assert_eq!(id, q.id);
+                assert_eq!(message, expected);
+            }
+            other => panic!("expected MalformedQuestion, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn question(question_type: QuestionType, answers: Vec<Answer2>) -> Question2 {
+        Question2 {
+            id: 1,
+            text: String::from("What is the capital of Malta?"),
+            question_type,
+            answers,
+            tags: Vec::new(),
+            prior_results: Vec::new(),
+        }
+    }
+
+    fn answer(variant: &str, correct: bool) -> Answer2 {
+        Answer2 {
+            variants: vec![String::from(variant)],
+            correct,
+            no_credit: false,
+            context: None,
+        }
+    }
+}
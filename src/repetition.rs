@@ -1,6 +1,9 @@
 /**
  * Choose the most optimal questions to ask based on past results.
  *
+ * The policy is pluggable through the `Scheduler` trait. The default scheduler sorts
+ * each question into a bucket:
+ *
  * Bucket 0: never asked before
  * Bucket 1: don't know at all, should ask immediately
  * Bucket 2: just learned, should ask within a day
@@ -15,12 +18,16 @@
  * Version: October 2019
  */
 use std::cmp;
+use std::collections::HashMap;
+
+use chrono::TimeZone;
 
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::Rng;
 
 use super::common;
-use super::common::TakeOptions;
+use super::common::{SchedulerKind, TakeOptions};
+use super::fsrs;
 use super::quiz::{Question, QuestionResult};
 
 // The percentage of questions that come from each bucket, expressed as integer
@@ -30,70 +37,552 @@ const BUCKET_ALLOCATION: [usize; 5] = [1, 2, 5, 5, 10];
 const UP_THRESHOLD: u64 = 900;
 // What percentage correct for a question to move down a bucket.
 const DOWN_THRESHOLD: u64 = 400;
+// The score that a slow but correct answer is given by the bucket scheduler. The
+// buckets only tell right answers from wrong ones, so a slow answer still moves a
+// question up.
+const BUCKET_SLOW_ANSWER_SCORE: u64 = UP_THRESHOLD;
+// How many days to wait after a question was last asked before it is due again, for
+// each bucket.
+const BUCKET_INTERVALS: [i64; 5] = [0, 0, 1, 7, 30];
+
+/// How many lapses make a question a leech, unless the quiz sets its own threshold.
+pub const DEFAULT_LEECH_THRESHOLD: usize = 8;
+
+// The ease factor that SM-2 assigns to a question before it has been answered.
+const SM2_INITIAL_EASE: f64 = 2.5;
+// SM-2 never lets the ease factor drop below this value.
+const SM2_MINIMUM_EASE: f64 = 1.3;
+// The score that a slow but correct answer is given by SM-2, a quality grade of 4
+// rather than 5.
+const SM2_SLOW_ANSWER_SCORE: u64 = 800;
+
+/// Decides which day a moment in time belongs to. Days are the user's days, not UTC
+/// days, so that a session at 1am can count towards the day before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calendar {
+    /// The hour of the day, from 0 to 23, at which one day rolls over into the next.
+    pub rollover_hour: u32,
+    /// The user's offset from UTC, or `None` to use the system's local time zone.
+    pub offset: Option<chrono::FixedOffset>,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar {
+            rollover_hour: 0,
+            offset: None,
+        }
+    }
+}
+
+impl Calendar {
+    /// Return the day that `time` falls on.
+    pub fn day_of(&self, time: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDate {
+        let time = time - chrono::Duration::hours(self.rollover_hour as i64);
+        match self.offset {
+            Some(offset) => time.with_timezone(&offset).date().naive_local(),
+            None => time.with_timezone(&chrono::Local).date().naive_local(),
+        }
+    }
+
+    /// Return the first moment of `day`.
+    pub fn start_of(&self, day: chrono::NaiveDate) -> chrono::DateTime<chrono::Utc> {
+        let start = day.and_hms(self.rollover_hour, 0, 0);
+        match self.offset {
+            Some(offset) => offset
+                .from_local_datetime(&start)
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+            None => {
+                // The rollover hour may be skipped entirely when the clocks go forward,
+                // in which case the day starts an hour later.
+                let local = chrono::Local.from_local_datetime(&start).earliest();
+                let local = local.or_else(|| {
+                    let start = start + chrono::Duration::hours(1);
+                    chrono::Local.from_local_datetime(&start).earliest()
+                });
+                local.unwrap().with_timezone(&chrono::Utc)
+            }
+        }
+    }
+
+    /// Return the first moment of the day after `now`.
+    pub fn start_of_next_day(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::DateTime<chrono::Utc> {
+        self.start_of(self.day_of(now).succ())
+    }
+
+    /// Move `due`, the due date of a question last reviewed at `last`, back to the
+    /// start of its day if that is a later day than the day of the review, so that
+    /// everything that is due on a given day can be reviewed in one sitting.
+    fn align_due(
+        &self,
+        last: chrono::DateTime<chrono::Utc>,
+        due: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::DateTime<chrono::Utc> {
+        let day = self.day_of(due);
+        if day > self.day_of(last) {
+            self.start_of(day)
+        } else {
+            due
+        }
+    }
+}
+
+/// A policy for deciding which questions are most useful to ask.
+pub trait Scheduler {
+    /// Choose up to `num_to_ask` questions from `candidates` based on their
+    /// `prior_results`, for a session taking place at `now`. The order of the returned
+    /// questions does not matter.
+    fn choose<'a>(
+        &self,
+        candidates: Vec<&'a Box<dyn Question>>,
+        num_to_ask: usize,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<&'a Box<dyn Question>>;
+
+    /// Return the time at which a question with the given results should next be
+    /// asked, or `None` if it has never been asked, in which case it is always due.
+    fn due(&self, results: &Vec<QuestionResult>) -> Option<chrono::DateTime<chrono::Utc>>;
+}
+
+/// Return the scheduler that implements the given algorithm, counting days according
+/// to `calendar`. `fsrs_parameters` is only used by the FSRS scheduler.
+pub fn get_scheduler(
+    kind: SchedulerKind,
+    fsrs_parameters: &fsrs::Parameters,
+    calendar: Calendar,
+) -> Box<dyn Scheduler> {
+    match kind {
+        SchedulerKind::Buckets => Box::new(BucketScheduler { calendar }),
+        SchedulerKind::Fsrs => Box::new(FsrsScheduler {
+            parameters: fsrs_parameters.clone(),
+            calendar,
+        }),
+        SchedulerKind::Sm2 => Box::new(Sm2Scheduler { calendar }),
+    }
+}
+
+/// Choose a set of questions for a session taking place at `now`, filtered by the
+/// command-line options. `rng` is used to shuffle the questions. If `suspend_after` is
+/// given, questions with at least that many lapses are never chosen.
+///
+/// With `--due`, only questions whose due date has passed are chosen. In cram mode,
+/// scheduling is bypassed entirely and every question that matches the filters is
+/// chosen, no matter when it is due.
+pub fn choose_questions<'a, R: Rng>(
+    questions: &'a Vec<Box<dyn Question>>,
+    scheduler: &dyn Scheduler,
+    options: &TakeOptions,
+    suspend_after: Option<usize>,
+    now: chrono::DateTime<chrono::Utc>,
+    rng: &mut R,
+) -> Vec<&'a Box<dyn Question>> {
+    let mut candidates = filter_questions(questions, options, suspend_after);
+    let mut chosen = if options.cram.is_some() {
+        candidates
+    } else {
+        candidates.retain(|q| !is_buried(q.as_ref(), now));
+        if options.due {
+            candidates.retain(|q| is_due(scheduler, q.as_ref(), now));
+        }
+        scheduler.choose(candidates, options.num_to_ask, now)
+    };
+
+    if options.in_order {
+        chosen.sort_by(cmp_questions_in_order);
+    } else {
+        chosen.shuffle(rng);
+    }
+    apply_order_constraints(&mut chosen);
+
+    chosen
+}
 
-/// Choose a set of questions, filtered by the command-line options.
-pub fn choose_questions<'a>(
+/// Return the earliest time at which one of the questions that match the command-line
+/// options will be due, or `None` if there are no such questions. Questions that have
+/// never been asked are due at `now`.
+pub fn next_due(
+    questions: &Vec<Box<dyn Question>>,
+    scheduler: &dyn Scheduler,
+    options: &TakeOptions,
+    suspend_after: Option<usize>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    filter_questions(questions, options, suspend_after)
+        .iter()
+        .map(|q| {
+            let due = scheduler.due(&q.get_common().prior_results).unwrap_or(now);
+            if let Some(buried_until) = buried_until(q.as_ref()) {
+                due.max(buried_until)
+            } else {
+                due
+            }
+        })
+        .min()
+}
+
+fn filter_questions<'a>(
     questions: &'a Vec<Box<dyn Question>>,
     options: &TakeOptions,
+    suspend_after: Option<usize>,
 ) -> Vec<&'a Box<dyn Question>> {
     let mut candidates = Vec::new();
     for question in questions.iter() {
+        if let Some(threshold) = suspend_after {
+            if count_lapses(question.as_ref()) >= threshold {
+                continue;
+            }
+        }
+
+        if is_suspended(question.as_ref()) {
+            continue;
+        }
+
         if common::filter_tags(&question.get_common().tags, &options.filter_opts) {
             candidates.push(question);
         }
     }
+    candidates
+}
+
+/// Return whether the user suspended `question` from inside a session and has not
+/// rewritten it since. A suspended question is never chosen, like a suspended leech.
+pub fn is_suspended(question: &dyn Question) -> bool {
+    if let Some(last) = question.get_common().prior_results.last() {
+        let rewritten = last
+            .text
+            .as_ref()
+            .map(|text| !question.has_text(text))
+            .unwrap_or(false);
+        last.suspended == Some(true) && !rewritten
+    } else {
+        false
+    }
+}
+
+/// Return the time until which the user buried `question`, if they buried it the last
+/// time it was asked.
+pub fn buried_until(question: &dyn Question) -> Option<chrono::DateTime<chrono::Utc>> {
+    question.get_common().prior_results.last()?.buried_until
+}
+
+fn is_buried(question: &dyn Question, now: chrono::DateTime<chrono::Utc>) -> bool {
+    if let Some(buried_until) = buried_until(question) {
+        now < buried_until
+    } else {
+        false
+    }
+}
+
+/// Return the number of times that a correct answer to `question` was followed by an
+/// incorrect one, ignoring results from before the question was last rewritten.
+///
+/// A question with enough lapses is a leech, which the quiz may ask to be suspended.
+/// Since older results do not count, rewriting a leech lifts the suspension.
+pub fn count_lapses(question: &dyn Question) -> usize {
+    let mut lapses = 0;
+    let mut previous_correct = false;
+    for result in question.get_common().prior_results.iter() {
+        if let Some(text) = &result.text {
+            if !question.has_text(text) {
+                lapses = 0;
+                previous_correct = false;
+                continue;
+            }
+        }
+
+        let score = result.recall_score(BUCKET_SLOW_ANSWER_SCORE);
+        if previous_correct && score <= DOWN_THRESHOLD {
+            lapses += 1;
+        }
+        previous_correct = score >= UP_THRESHOLD;
+    }
+    lapses
+}
+
+pub fn is_due(
+    scheduler: &dyn Scheduler,
+    question: &dyn Question,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    if let Some(due) = scheduler.due(&question.get_common().prior_results) {
+        due <= now
+    } else {
+        true
+    }
+}
+
+/// The default scheduler, which allocates a fixed share of each session to each bucket.
+///
+/// A question in Bucket 1 is due immediately, and questions in Buckets 2, 3 and 4 are
+/// due one day, one week and one month after they were last asked.
+pub struct BucketScheduler {
+    pub calendar: Calendar,
+}
+
+impl Scheduler for BucketScheduler {
+    fn choose<'a>(
+        &self,
+        candidates: Vec<&'a Box<dyn Question>>,
+        num_to_ask: usize,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<&'a Box<dyn Question>> {
+        let mut buckets = Vec::new();
+        for _ in 0..BUCKET_ALLOCATION.len() {
+            buckets.push(Vec::new());
+        }
+
+        let today = self.calendar.day_of(now);
+        for question in candidates.iter() {
+            let results = &question.get_common().prior_results;
+            buckets[get_bucket(results, &self.calendar, today)].push(question);
+        }
+
+        for bucket in buckets.iter_mut() {
+            bucket.sort_by(cmp_questions_oldest_first);
+        }
+
+        let mut chosen = Vec::new();
+        let mut cumulative_allocation = 0;
+        for i in 0..BUCKET_ALLOCATION.len() {
+            let mut allocation = num_to_ask / BUCKET_ALLOCATION[i];
+            if i == BUCKET_ALLOCATION.len() - 1 {
+                allocation = num_to_ask - chosen.len();
+            } else {
+                // If previous buckets didn't have enough questions to fill their
+                // allocations, spill over the extra question allocation into this
+                // bucket.
+                allocation += cumulative_allocation - chosen.len();
+            }
+            allocation = cmp::min(allocation, buckets[i].len());
+            allocation = cmp::min(allocation, num_to_ask - chosen.len());
+            for j in 0..allocation {
+                chosen.push(*buckets[i][j]);
+            }
+            cumulative_allocation += allocation;
+        }
+
+        chosen
+    }
+
+    fn due(&self, results: &Vec<QuestionResult>) -> Option<chrono::DateTime<chrono::Utc>> {
+        let last = results.last()?;
+        let interval = BUCKET_INTERVALS[get_earned_bucket(results)];
+        let due = last.time_asked + chrono::Duration::days(interval);
+        Some(self.calendar.align_due(last.time_asked, due))
+    }
+}
+
+/// An implementation of the SM-2 algorithm by Piotr Wozniak, which asks the questions
+/// that are most overdue.
+pub struct Sm2Scheduler {
+    pub calendar: Calendar,
+}
 
-    let mut buckets = Vec::new();
-    for _ in 0..BUCKET_ALLOCATION.len() {
-        buckets.push(Vec::new());
+impl Scheduler for Sm2Scheduler {
+    fn choose<'a>(
+        &self,
+        candidates: Vec<&'a Box<dyn Question>>,
+        num_to_ask: usize,
+        _now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<&'a Box<dyn Question>> {
+        // Questions that have never been asked have no due date and sort first, then
+        // the rest are sorted so that the most overdue come first.
+        let mut ranked: Vec<_> = candidates
+            .into_iter()
+            .map(|q| (self.due(&q.get_common().prior_results), q))
+            .collect();
+        ranked.sort_by(|a, b| a.0.cmp(&b.0));
+        ranked.truncate(num_to_ask);
+        ranked.into_iter().map(|(_, q)| q).collect()
     }
 
-    for question in candidates.iter() {
-        buckets[get_bucket(&question.get_common().prior_results)].push(question);
+    fn due(&self, results: &Vec<QuestionResult>) -> Option<chrono::DateTime<chrono::Utc>> {
+        let last = results.last()?;
+        let due = sm2_due(results)?;
+        Some(self.calendar.align_due(last.time_asked, due))
     }
+}
 
-    for bucket in buckets.iter_mut() {
-        bucket.sort_by(cmp_questions_oldest_first);
+/// A scheduler that asks the questions with the lowest estimated probability of recall.
+pub struct FsrsScheduler {
+    pub parameters: fsrs::Parameters,
+    pub calendar: Calendar,
+}
+
+impl Scheduler for FsrsScheduler {
+    fn choose<'a>(
+        &self,
+        candidates: Vec<&'a Box<dyn Question>>,
+        num_to_ask: usize,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<&'a Box<dyn Question>> {
+        // Questions that have never been asked are given a probability of recall of
+        // zero so that they come first.
+        let mut ranked: Vec<_> = candidates
+            .into_iter()
+            .map(|q| {
+                let state = fsrs::memory_state(&self.parameters, &q.get_common().prior_results);
+                let recall = state
+                    .map(|state| fsrs::retrievability(&state, now))
+                    .unwrap_or(0.0);
+                (recall, q)
+            })
+            .collect();
+        ranked.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(cmp::Ordering::Equal));
+        ranked.truncate(num_to_ask);
+        ranked.into_iter().map(|(_, q)| q).collect()
+    }
+
+    /// A question is due when its probability of recall falls to 90%, which by
+    /// definition happens after a number of days equal to its stability.
+    fn due(&self, results: &Vec<QuestionResult>) -> Option<chrono::DateTime<chrono::Utc>> {
+        let state = fsrs::memory_state(&self.parameters, results)?;
+        let interval = chrono::Duration::seconds((state.stability * 86400.0) as i64);
+        let due = state.last_review + interval;
+        Some(self.calendar.align_due(state.last_review, due))
     }
+}
 
-    let mut chosen = Vec::new();
-    let mut cumulative_allocation = 0;
-    for i in 0..BUCKET_ALLOCATION.len() {
-        let mut allocation = options.num_to_ask / BUCKET_ALLOCATION[i];
-        if i == BUCKET_ALLOCATION.len() - 1 {
-            allocation = options.num_to_ask - chosen.len();
+/// The state of a question under the SM-2 algorithm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sm2State {
+    pub ease: f64,
+    /// Number of days to wait after the last review before asking the question again.
+    pub interval: f64,
+    /// Number of correct answers in a row.
+    pub repetitions: usize,
+}
+
+/// Compute the SM-2 state of a question by replaying its results in order. Scores out
+/// of 1,000 are converted to SM-2's quality grades of 0 to 5, and a grade of 3 or more
+/// counts as a correct answer.
+pub fn sm2_state(results: &Vec<QuestionResult>) -> Sm2State {
+    let mut state = Sm2State {
+        ease: SM2_INITIAL_EASE,
+        interval: 0.0,
+        repetitions: 0,
+    };
+    for result in results.iter() {
+        let quality = ((result.recall_score(SM2_SLOW_ANSWER_SCORE) as f64) * 5.0 / 1000.0).round();
+        if quality >= 3.0 {
+            state.interval = match state.repetitions {
+                0 => 1.0,
+                1 => 6.0,
+                _ => (state.interval * state.ease).round(),
+            };
+            state.repetitions += 1;
         } else {
-            // If previous buckets didn't have enough questions to fill their
-            // allocations, spill over the extra question allocation into this bucket.
-            allocation += cumulative_allocation - chosen.len();
+            state.interval = 1.0;
+            state.repetitions = 0;
         }
-        allocation = cmp::min(allocation, buckets[i].len());
-        allocation = cmp::min(allocation, options.num_to_ask - chosen.len());
-        for j in 0..allocation {
-            chosen.push(*buckets[i][j]);
+
+        let penalty = 5.0 - quality;
+        state.ease += 0.1 - penalty * (0.08 + penalty * 0.02);
+        if state.ease < SM2_MINIMUM_EASE {
+            state.ease = SM2_MINIMUM_EASE;
         }
-        cumulative_allocation += allocation;
     }
+    state
+}
 
-    if options.in_order {
-        chosen.sort_by(cmp_questions_in_order);
-    } else {
-        let mut rng = thread_rng();
-        chosen.shuffle(&mut rng);
+/// Return the time at which a question is next due under SM-2, or `None` if it has
+/// never been asked.
+fn sm2_due(results: &Vec<QuestionResult>) -> Option<chrono::DateTime<chrono::Utc>> {
+    let last = results.last()?;
+    let state = sm2_state(results);
+    let interval = chrono::Duration::seconds((state.interval * 86400.0) as i64);
+    Some(last.time_asked + interval)
+}
+
+/// Reorder `chosen` so that each question comes after the chosen questions named by
+/// its `after` field. Only the questions that take part in a constraint are moved, and
+/// they keep the positions they already occupied, so the rest of the order is left
+/// untouched.
+///
+/// A constraint that names a question that was not chosen is dropped rather than
+/// pulling that question in, so that the bucket allocation is not disturbed.
+fn apply_order_constraints(chosen: &mut Vec<&Box<dyn Question>>) {
+    let mut positions = HashMap::new();
+    for (i, q) in chosen.iter().enumerate() {
+        positions.insert(q.get_common().id.as_str(), i);
     }
 
-    chosen
+    // `predecessors[i]` holds the positions of the chosen questions that must come
+    // before the question at position `i`.
+    let mut predecessors = vec![Vec::new(); chosen.len()];
+    let mut constrained = vec![false; chosen.len()];
+    for (i, q) in chosen.iter().enumerate() {
+        for id in q.get_common().after.iter() {
+            if let Some(&j) = positions.get(id.as_str()) {
+                predecessors[i].push(j);
+                constrained[i] = true;
+                constrained[j] = true;
+            }
+        }
+    }
+
+    let slots: Vec<usize> = (0..chosen.len()).filter(|&i| constrained[i]).collect();
+    if slots.is_empty() {
+        return;
+    }
+
+    // Topological sort of the constrained questions. Ties are broken by current
+    // position so that the random order from the shuffle is preserved where possible.
+    let mut placed = vec![false; chosen.len()];
+    let mut order = Vec::new();
+    while order.len() < slots.len() {
+        let next = slots
+            .iter()
+            .find(|&&i| !placed[i] && predecessors[i].iter().all(|&j| placed[j]));
+        if let Some(&next) = next {
+            placed[next] = true;
+            order.push(chosen[next]);
+        } else {
+            // Cycles are rejected when the quiz is parsed, so this should never happen.
+            return;
+        }
+    }
+
+    for (slot, q) in slots.iter().zip(order) {
+        chosen[*slot] = q;
+    }
+}
+
+fn get_bucket(
+    results: &Vec<QuestionResult>,
+    calendar: &Calendar,
+    today: chrono::NaiveDate,
+) -> usize {
+    // A question answered correctly earlier today goes in the highest bucket, so that
+    // it isn't asked again so soon.
+    let answered_today = results
+        .last()
+        .map(|r| {
+            r.recall_score(BUCKET_SLOW_ANSWER_SCORE) >= UP_THRESHOLD
+                && calendar.day_of(r.time_asked) == today
+        })
+        .unwrap_or(false);
+    if answered_today {
+        BUCKET_ALLOCATION.len() - 1
+    } else {
+        get_earned_bucket(results)
+    }
 }
 
-fn get_bucket(results: &Vec<QuestionResult>) -> usize {
-    // TODO(2019-11-12): If answered correctly in the past 24 hours or so, put it in the
-    // highest bucket.
+/// Returns the bucket that a question's history has earned it, ignoring how recently it
+/// was answered.
+fn get_earned_bucket(results: &Vec<QuestionResult>) -> usize {
     let mut bucket = 0;
     for result in results.iter() {
         // 90% and 40% are arbitrary thresholds that I may need to adjust.
-        if result.score >= UP_THRESHOLD && bucket < BUCKET_ALLOCATION.len() - 1 {
+        let score = result.recall_score(BUCKET_SLOW_ANSWER_SCORE);
+        if score >= UP_THRESHOLD && bucket < BUCKET_ALLOCATION.len() - 1 {
             bucket += 1;
-        } else if result.score <= DOWN_THRESHOLD && bucket > 0 {
+        } else if score <= DOWN_THRESHOLD && bucket > 0 {
             bucket -= 1;
         }
     }
@@ -140,3 +629,73 @@ fn cmp_questions_oldest_first(a: &&&Box<dyn Question>, b: &&&Box<dyn Question>)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sm2_intervals_grow_with_correct_answers() {
+        let state = sm2_state(&results(&[1000]));
+        assert_eq!(state.interval, 1.0);
+        assert_eq!(state.repetitions, 1);
+
+        let state = sm2_state(&results(&[1000, 1000]));
+        assert_eq!(state.interval, 6.0);
+
+        let state = sm2_state(&results(&[1000, 1000, 1000]));
+        assert_eq!(state.interval, 16.0);
+        assert!(state.ease > SM2_INITIAL_EASE);
+    }
+
+    #[test]
+    fn sm2_resets_after_incorrect_answer() {
+        let state = sm2_state(&results(&[1000, 1000, 0]));
+        assert_eq!(state.interval, 1.0);
+        assert_eq!(state.repetitions, 0);
+
+        let state = sm2_state(&results(&[0, 0, 0, 0, 0, 0]));
+        assert_eq!(state.ease, SM2_MINIMUM_EASE);
+    }
+
+    #[test]
+    fn slow_correct_answers_count_as_weaker_recall() {
+        let mut slow = results(&[1000, 1000]);
+        for result in slow.iter_mut() {
+            result.response_time_ms = Some(60_000);
+        }
+        let fast = results(&[1000, 1000]);
+        // The buckets only tell right answers from wrong ones.
+        assert_eq!(get_earned_bucket(&fast), 2);
+        assert_eq!(get_earned_bucket(&slow), 2);
+        assert!(sm2_state(&slow).ease < sm2_state(&fast).ease);
+
+        let parameters = fsrs::Parameters::default();
+        let slow_state = fsrs::memory_state(&parameters, &slow).unwrap();
+        let fast_state = fsrs::memory_state(&parameters, &fast).unwrap();
+        assert!(slow_state.stability < fast_state.stability);
+    }
+
+    #[test]
+    fn days_roll_over_at_the_configured_hour() {
+        let calendar = Calendar {
+            rollover_hour: 4,
+            offset: chrono::FixedOffset::east_opt(-5 * 3600),
+        };
+        let day = chrono::NaiveDate::from_ymd(2030, 1, 1);
+        // 1am on January 2nd in UTC-5 still counts as January 1st.
+        let late = chrono::Utc.ymd(2030, 1, 2).and_hms(6, 0, 0);
+        assert_eq!(calendar.day_of(late), day);
+        assert_eq!(
+            calendar.start_of_next_day(late),
+            chrono::Utc.ymd(2030, 1, 2).and_hms(9, 0, 0)
+        );
+    }
+
+    fn results(scores: &[u64]) -> Vec<QuestionResult> {
+        scores
+            .iter()
+            .map(|score| QuestionResult::new("1", chrono::Utc::now(), *score))
+            .collect()
+    }
+}
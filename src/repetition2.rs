@@ -37,14 +37,88 @@ pub fn choose_questions<'a>(
     options: &TakeOptions,
 ) -> Vec<&'a Question2> {
     let mut candidates = Vec::new();
-    // TODO(2021-06-30): Filter by command-line options.
     for question in questions.iter() {
-        candidates.push(question);
+        if common::filter_tags(&question.tags, &options.filter_opts) {
+            candidates.push(question);
+        }
     }
 
-    // TODO(2021-06-30): Port over v1 algorithm for spaced repetition.
-    let mut rng = thread_rng();
-    candidates.shuffle(&mut rng);
-    candidates.truncate(options.num_to_ask);
-    candidates
+    let mut buckets = Vec::new();
+    for _ in 0..BUCKET_ALLOCATION.len() {
+        buckets.push(Vec::new());
+    }
+
+    for question in candidates.iter() {
+        buckets[get_bucket(&question.prior_results)].push(*question);
+    }
+
+    for bucket in buckets.iter_mut() {
+        bucket.sort_by(cmp_questions_oldest_first);
+    }
+
+    let mut chosen = Vec::new();
+    let mut cumulative_allocation = 0;
+    for i in 0..BUCKET_ALLOCATION.len() {
+        let mut allocation = options.num_to_ask / BUCKET_ALLOCATION[i];
+        if i == BUCKET_ALLOCATION.len() - 1 {
+            allocation = options.num_to_ask - chosen.len();
+        } else {
+            // If previous buckets didn't have enough questions to fill their
+            // allocations, spill over the extra question allocation into this bucket.
+            allocation += cumulative_allocation - chosen.len();
+        }
+        allocation = cmp::min(allocation, buckets[i].len());
+        allocation = cmp::min(allocation, options.num_to_ask - chosen.len());
+        for j in 0..allocation {
+            chosen.push(buckets[i][j]);
+        }
+        cumulative_allocation += allocation;
+    }
+
+    if options.in_order {
+        // Question IDs are assigned in the order that the questions were added to the
+        // database, which for migrated quizzes is the order of the original file.
+        chosen.sort_by_key(|q| q.id);
+    } else {
+        let mut rng = thread_rng();
+        chosen.shuffle(&mut rng);
+    }
+
+    chosen
+}
+
+fn get_bucket(results: &Vec<QuestionResult2>) -> usize {
+    let mut bucket = 0;
+    for result in results.iter() {
+        if result.score >= UP_THRESHOLD && bucket < BUCKET_ALLOCATION.len() - 1 {
+            bucket += 1;
+        } else if result.score <= DOWN_THRESHOLD && bucket > 0 {
+            bucket -= 1;
+        }
+    }
+    bucket
+}
+
+/// Comparison function that sorts an array of `Question2` objects so that the questions
+/// that were least recently asked appear first. Questions that have never been asked
+/// will appear at the very front.
+fn cmp_questions_oldest_first(a: &&Question2, b: &&Question2) -> cmp::Ordering {
+    // NOTE: This method assumes that the `prior_results` field of `Question2` objects
+    // is ordered chronologically, which `persistence2::load_results` guarantees.
+    if let Some(a_last) = a.prior_results.last() {
+        if let Some(b_last) = b.prior_results.last() {
+            a_last
+                .time_asked
+                .partial_cmp(&b_last.time_asked)
+                .unwrap_or(cmp::Ordering::Equal)
+        } else {
+            cmp::Ordering::Greater
+        }
+    } else {
+        if b.prior_results.len() > 0 {
+            cmp::Ordering::Less
+        } else {
+            cmp::Ordering::Equal
+        }
+    }
 }
@@ -0,0 +1,225 @@
+/**
+ * Evaluate schedulers offline by simulating many days of sessions against a synthetic
+ * learner.
+ *
+ * The learner's memory of each question decays exponentially: the probability of
+ * recalling a question `t` days after it was last asked is `exp(-t / strength)`. A
+ * question that has never been asked is always answered incorrectly. A correct answer
+ * multiplies the question's strength by a constant growth factor, and an incorrect
+ * answer resets the strength to its initial value. Each question's initial strength is
+ * scaled by a random factor between 0.5 and 1.5 so that some questions are harder than
+ * others.
+ *
+ * The simulation runs one session per day, at the same time each day, through the same
+ * `repetition::choose_questions` function used for real sessions. All randomness comes
+ * from a single seeded generator, so a simulation can be reproduced exactly.
+ *
+ * Author:  Ian Fisher (iafisher@fastmail.com)
+ * Version: October 2019
+ */
+use std::io::Write;
+
+use colored::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::common::{self, QuizError, Result, SchedulerKind, SimulateOptions};
+use super::iohelper::prettyprint_colored;
+use super::quiz::{QuestionResult, Quiz};
+use super::repetition;
+
+/// Scores at or above this count as a correct answer when replaying real results.
+const CORRECT_THRESHOLD: u64 = 500;
+
+/// The outcome of a simulation.
+pub struct SimulationResult {
+    pub kind: SchedulerKind,
+    /// The indices of the questions that match the tag filters.
+    pub candidates: Vec<usize>,
+    /// The learner's mean probability of recall over the candidate questions at the
+    /// start of each day, before that day's session.
+    pub retention_per_day: Vec<f64>,
+    /// The mean probability of recall after the last session.
+    pub final_retention: f64,
+    pub reviews_per_day: Vec<usize>,
+    /// How many times each question in the quiz was asked.
+    pub times_asked: Vec<usize>,
+}
+
+/// The synthetic learner.
+struct Learner {
+    initial_strength: Vec<f64>,
+    strength: Vec<f64>,
+    last_seen: Vec<Option<chrono::DateTime<chrono::Utc>>>,
+    growth: f64,
+}
+
+impl Learner {
+    fn new<R: Rng>(n: usize, options: &SimulateOptions, rng: &mut R) -> Self {
+        let initial_strength: Vec<f64> = (0..n)
+            .map(|_| options.initial_strength * rng.gen_range(0.5, 1.5))
+            .collect();
+        Self {
+            strength: initial_strength.clone(),
+            initial_strength,
+            last_seen: vec![None; n],
+            growth: options.growth,
+        }
+    }
+
+    fn recall_probability(&self, i: usize, now: chrono::DateTime<chrono::Utc>) -> f64 {
+        if let Some(last_seen) = self.last_seen[i] {
+            let days = ((now - last_seen).num_seconds().max(0) as f64) / 86400.0;
+            (-days / self.strength[i]).exp()
+        } else {
+            0.0
+        }
+    }
+
+    fn review(&mut self, i: usize, now: chrono::DateTime<chrono::Utc>, correct: bool) {
+        if correct {
+            self.strength[i] *= self.growth;
+        } else {
+            self.strength[i] = self.initial_strength[i];
+        }
+        self.last_seen[i] = Some(now);
+    }
+}
+
+/// Simulate `options.days` days of sessions on `quiz`, starting at `start`. Unless
+/// `options.replay` is set, the quiz's real results are discarded first.
+pub fn simulate(
+    quiz: &mut Quiz,
+    options: &SimulateOptions,
+    start: chrono::DateTime<chrono::Utc>,
+) -> SimulationResult {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let n = quiz.questions.len();
+    let mut learner = Learner::new(n, options, &mut rng);
+
+    for (i, q) in quiz.questions.iter_mut().enumerate() {
+        let common = q.get_common_mut();
+        if options.replay {
+            for result in common.prior_results.iter() {
+                learner.review(i, result.time_asked, result.score >= CORRECT_THRESHOLD);
+            }
+        } else {
+            common.prior_results.clear();
+        }
+    }
+
+    let kind = quiz.scheduler_kind(options.take.scheduler);
+    let scheduler = repetition::get_scheduler(kind, &quiz.fsrs_parameters, quiz.calendar);
+
+    let candidates: Vec<usize> = (0..n)
+        .filter(|i| {
+            common::filter_tags(
+                &quiz.questions[*i].get_common().tags,
+                &options.take.filter_opts,
+            )
+        })
+        .collect();
+
+    let mut retention_per_day = Vec::new();
+    let mut reviews_per_day = Vec::new();
+    let mut times_asked = vec![0; n];
+    for day in 0..options.days {
+        let now = start + chrono::Duration::days(day as i64);
+        retention_per_day.push(mean_retention(&learner, &candidates, now));
+
+        let chosen: Vec<usize> = repetition::choose_questions(
+            &quiz.questions,
+            scheduler.as_ref(),
+            &options.take,
+            quiz.suspend_after(),
+            now,
+            &mut rng,
+        )
+        .iter()
+        .map(|q| quiz.find(&q.get_common().id).unwrap())
+        .collect();
+
+        for (j, i) in chosen.iter().enumerate() {
+            // Space the answers a few seconds apart, as in a real session.
+            let time_asked = now + chrono::Duration::seconds(10 * j as i64);
+            let correct = rng.gen::<f64>() < learner.recall_probability(*i, time_asked);
+            learner.review(*i, time_asked, correct);
+            times_asked[*i] += 1;
+
+            let common = quiz.questions[*i].get_common_mut();
+            let score = if correct { 1000 } else { 0 };
+            common
+                .prior_results
+                .push(QuestionResult::new(&common.id, time_asked, score));
+        }
+        reviews_per_day.push(chosen.len());
+    }
+
+    let end = start + chrono::Duration::days(options.days as i64);
+    SimulationResult {
+        kind,
+        final_retention: mean_retention(&learner, &candidates, end),
+        candidates,
+        retention_per_day,
+        reviews_per_day,
+        times_asked,
+    }
+}
+
+/// Print a summary of a simulation to standard output.
+pub fn print_report(
+    quiz: &Quiz,
+    options: &SimulateOptions,
+    result: &SimulationResult,
+) -> Result<()> {
+    my_println!(
+        "Simulated {} days with the {} scheduler (seed {}).\n",
+        options.days,
+        result.kind.name(),
+        options.seed
+    )?;
+
+    let days = result.reviews_per_day.len().max(1) as f64;
+    let average_retention = result.retention_per_day.iter().sum::<f64>() / days;
+    let total_reviews: usize = result.reviews_per_day.iter().sum();
+    my_println!("Average retention: {:>5.1}%", average_retention * 100.0)?;
+    my_println!(
+        "Final retention:   {:>5.1}%",
+        result.final_retention * 100.0
+    )?;
+    my_println!(
+        "Reviews per day:   {:>5.1} (min {}, max {})",
+        (total_reviews as f64) / days,
+        result.reviews_per_day.iter().min().unwrap_or(&0),
+        result.reviews_per_day.iter().max().unwrap_or(&0)
+    )?;
+
+    my_println!("\nTimes asked:")?;
+    for i in result.candidates.iter() {
+        let q = &quiz.questions[*i];
+        let prefix = format!("{:>5}   ", result.times_asked[*i]);
+        prettyprint_colored(
+            &format!("[{}] {}", q.get_common().id, q.get_text()),
+            &prefix,
+            None,
+            Some(Color::Cyan),
+        )?;
+    }
+    Ok(())
+}
+
+fn mean_retention(
+    learner: &Learner,
+    candidates: &Vec<usize>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> f64 {
+    if candidates.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = candidates
+        .iter()
+        .map(|i| learner.recall_probability(*i, now))
+        .sum();
+    total / (candidates.len() as f64)
+}
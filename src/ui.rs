@@ -32,6 +32,17 @@ impl CmdUI {
         self.number += 1;
     }
 
+    /// Start timing the current question again, e.g. after it is asked again following
+    /// a command like `!edit`.
+    pub fn restart_timer(&mut self) {
+        self.time_started = time::Instant::now();
+    }
+
+    /// Return how long it has been since the current question was asked.
+    pub fn elapsed(&self) -> time::Duration {
+        self.time_started.elapsed()
+    }
+
     pub fn text(&mut self, text: &str) -> Result<()> {
         my_print!("\n")?;
         let prefix = format!("  ({}) ", self.number);
@@ -44,6 +55,13 @@ impl CmdUI {
         if let Some(response) = response.as_ref() {
             if response == "!!" {
                 return Err(QuizError::SignalMarkCorrect);
+            } else if response == "!suspend" {
+                return Err(QuizError::SignalSuspend);
+            } else if response == "!bury" {
+                return Err(QuizError::SignalBury);
+            } else if response == "!flag" || response.starts_with("!flag ") {
+                let note = response["!flag".len()..].trim();
+                return Err(QuizError::SignalFlag(String::from(note)));
             } else if "!edit".starts_with(response) {
                 return Err(QuizError::SignalEdit);
             }
@@ -93,6 +111,26 @@ impl CmdUI {
         my_print!("\n")
     }
 
+    /// Tell the user that no questions are due, and how long until the next one is.
+    pub fn nothing_due(&mut self, wait: chrono::Duration) -> Result<()> {
+        let wait = if wait.num_days() > 0 {
+            format!("{}d", wait.num_days())
+        } else if wait.num_hours() > 0 {
+            format!("{}h", wait.num_hours())
+        } else {
+            format!("{}m", wait.num_minutes() + 1)
+        };
+        my_println!("Nothing due, next review in {}.", wait)
+    }
+
+    /// Ask the user whether to resume the session that was interrupted last time.
+    pub fn confirm_resume(&mut self) -> Result<bool> {
+        let response = prompt("Resume the interrupted session? [y/n] ")?;
+        Ok(response
+            .map(|r| r.to_lowercase().starts_with("y"))
+            .unwrap_or(false))
+    }
+
     pub fn instructions(&mut self, text: &str) -> Result<()> {
         my_print!("\n")?;
         prettyprint_colored(&text, "  ", Some(Color::BrightBlue), None)?;
@@ -127,6 +165,26 @@ impl CmdUI {
         Ok(())
     }
 
+    /// Summarize a cram session. Each element of `mastery` is the number of attempts at
+    /// a question, whether it was mastered, and the text of the question.
+    pub fn mastery(&mut self, mastery: &Vec<(usize, bool, String)>) -> Result<()> {
+        let mastered = mastery.iter().filter(|(_, m, _)| *m).count();
+        my_println!("\nMastered {} of {} questions.", mastered, mastery.len())?;
+        for (attempts, mastered, text) in mastery.iter() {
+            let prefix = format!(
+                "  {:>3} attempt{}   ",
+                attempts,
+                if *attempts == 1 { " " } else { "s" }
+            );
+            if *mastered {
+                prettyprint_colored(text, &prefix, None, Some(Color::Cyan))?;
+            } else {
+                prettyprint_colored(text, &prefix, Some(Color::Red), Some(Color::Cyan))?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn launch_editor(&mut self, location: &Location) -> Result<()> {
         let editor = ::std::env::var("EDITOR").unwrap_or(String::from("nano"));
         let mut cmd = Command::new(&editor);